@@ -0,0 +1,98 @@
+//! Compare the lattice against the straightforward recursive parser
+//! on repetitive inputs, where the same suffix is reached many times.
+//!
+//! Run with `cargo bench`.
+
+extern crate number_words;
+
+use number_words::{default_config, Config, Parser};
+use std::cmp;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Recursive parser without memoization, for reference.
+struct Naive {
+    max_lookahead: usize,
+    table: HashMap<Vec<char>, char>
+}
+
+impl Naive {
+    fn new(config: &Config) -> Naive {
+        Naive {
+            max_lookahead: config.iter().map(|(s, _)| s.len()).fold(0, cmp::max),
+            table: config
+                .iter()
+                .map(|&(ref s, c)| (s.chars().collect(), c))
+                .collect()
+        }
+    }
+
+    fn parse(&self, ds: &[char]) -> Vec<String> {
+        if ds.is_empty() {
+            return vec![String::new()];
+        }
+        (1 ..= cmp::min(self.max_lookahead, ds.len()))
+            .filter_map(|i| self.table.get(&ds[..i]).map(|&c| (i, c)))
+            .flat_map(|(i, c)| {
+                self.parse(&ds[i..])
+                    .into_iter()
+                    .map(move |s| c.to_string() + &s)
+            })
+            .collect()
+    }
+
+    fn count(&self, ds: &[char]) -> u128 {
+        if ds.is_empty() {
+            return 1;
+        }
+        (1 ..= cmp::min(self.max_lookahead, ds.len()))
+            .filter(|&i| self.table.contains_key(&ds[..i]))
+            .map(|i| self.count(&ds[i..]))
+            .sum()
+    }
+}
+
+fn time<T>(f: impl Fn() -> T) -> (Duration, T) {
+    const RUNS: u32 = 5;
+    let start = Instant::now();
+    for _ in 1 .. RUNS {
+        f();
+    }
+    let result = f();
+    (start.elapsed() / RUNS, result)
+}
+
+fn compare<T: PartialEq + std::fmt::Debug>(name: &str,
+                                           naive: impl Fn() -> T,
+                                           lattice: impl Fn() -> T) {
+    let (naive_time, naive_result) = time(naive);
+    let (lattice_time, lattice_result) = time(lattice);
+    assert_eq!(naive_result, lattice_result);
+    println!("{:<24} naive {:>12?}   lattice {:>12?}",
+             name, naive_time, lattice_time);
+}
+
+fn main() {
+    let config = default_config();
+    let naive = Naive::new(&config);
+    let parser = Parser::new(&config);
+
+    // Every suffix is ambiguous, and nothing parses at the end.
+    let dead_end = "1".repeat(24) + "00";
+    let ds = dead_end.chars().collect::<Vec<char>>();
+    compare("parse 1^24 00",
+            || naive.parse(&ds),
+            || parser.parse(&dead_end));
+
+    let ones = "1".repeat(18);
+    let ds = ones.chars().collect::<Vec<char>>();
+    compare("parse 1^18",
+            || naive.parse(&ds),
+            || parser.parse(&ones));
+
+    let ones = "1".repeat(30);
+    let ds = ones.chars().collect::<Vec<char>>();
+    compare("count 1^30",
+            || Some(naive.count(&ds)),
+            || parser.checked_count(&ones));
+}
@@ -0,0 +1,171 @@
+//! Properties of a table of tokens, independent of any input.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::hash::Hash;
+
+use super::Parser;
+
+/// Whether every input has at most one parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decodability<I = char, O = char> {
+    Unique,
+    Ambiguous(Ambiguity<I, O>)
+}
+
+/// Shortest input with two different parses, each given as its
+/// tokens with their outputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ambiguity<I = char, O = char> {
+    pub input: Vec<I>,
+    pub first: Vec<(Vec<I>, Vec<O>)>,
+    pub second: Vec<(Vec<I>, Vec<O>)>
+}
+
+/// Two parses of the same input so far, one ahead of the other by
+/// `dangling`.
+struct State<I> {
+    dangling: Vec<I>,
+
+    /// Length of the input covered by the leading parse.
+    len: usize,
+
+    /// Token indices. The first parse starts with the shorter token.
+    parses: [Vec<usize>; 2],
+    leader: usize
+}
+
+impl<I: Eq + Hash + Clone, O: Clone> Parser<I, O> {
+    /// Whether no token is a prefix of another, so that each input has
+    /// at most one parse and it can be found greedily.
+    pub fn is_prefix_free(&self) -> bool {
+        self.prefix_violations().is_empty()
+    }
+
+    /// Every pair of tokens where the first is a proper prefix of the
+    /// second, in order of the second in the table.
+    pub fn prefix_violations(&self) -> Vec<(Vec<I>, Vec<I>)> {
+        self.trie
+            .entries()
+            .into_iter()
+            .filter(|(_, entry)| !entry.outputs.is_empty())
+            .flat_map(|(token, _)| {
+                self.trie
+                    .prefixes(&token)
+                    .filter(|&(len, entry)| len < token.len() && !entry.outputs.is_empty())
+                    .map(|(len, _)| (token[..len].to_vec(), token.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Sardinas–Patterson test: follow every way two parses can
+    /// start differently and try to catch up with each other.
+    /// Dangling suffixes are explored shortest input first, so the
+    /// witness of an ambiguity is as short as possible.
+    pub fn decodability(&self) -> Decodability<I, O> {
+        let entries = self.trie
+            .entries()
+            .into_iter()
+            .map(|(token, entry)| (token, &entry.outputs))
+            .filter(|(token, outputs)| !token.is_empty() && !outputs.is_empty())
+            .collect::<Vec<_>>();
+
+        let witness = |parses: &[Vec<usize>; 2]| {
+            let parse = |indices: &[usize]| {
+                indices
+                    .iter()
+                    .map(|&i| (entries[i].0.clone(), entries[i].1[0].clone()))
+                    .collect()
+            };
+            Ambiguity {
+                input: parses[0]
+                    .iter()
+                    .flat_map(|&i| entries[i].0.iter().cloned())
+                    .collect(),
+                first: parse(&parses[0]),
+                second: parse(&parses[1])
+            }
+        };
+
+        // A token with several outputs is ambiguous by itself.
+        let mut shortest: Option<Ambiguity<I, O>> = entries
+            .iter()
+            .filter(|(_, outputs)| outputs.len() > 1)
+            .min_by_key(|(token, _)| token.len())
+            .map(|&(ref token, outputs)| {
+                let piece = |output: &Vec<O>| vec![(token.clone(), output.clone())];
+                Ambiguity {
+                    input: token.clone(),
+                    first: piece(&outputs[0]),
+                    second: piece(&outputs[1])
+                }
+            });
+
+        // Dijkstra on input length; the counter keeps ties in the
+        // order of the table.
+        let mut states = Vec::new();
+        let mut queue = BinaryHeap::new();
+        for (a, (long, _)) in entries.iter().enumerate() {
+            for (b, (short, _)) in entries.iter().enumerate() {
+                if short.len() < long.len() && long.starts_with(short) {
+                    queue.push(Reverse((long.len(), states.len())));
+                    states.push(State {
+                        dangling: long[short.len()..].to_vec(),
+                        len: long.len(),
+                        parses: [vec![b], vec![a]],
+                        leader: 1
+                    });
+                }
+            }
+        }
+
+        let mut settled = HashSet::new();
+        while let Some(Reverse((len, id))) = queue.pop() {
+            if matches!(shortest, Some(ref s) if s.input.len() <= len) {
+                break;
+            }
+            if !settled.insert(states[id].dangling.clone()) {
+                continue;
+            }
+
+            for (t, (token, _)) in entries.iter().enumerate() {
+                let state = &states[id];
+                let follower = 1 - state.leader;
+                let mut parses = state.parses.clone();
+                parses[follower].push(t);
+
+                let next = if *token == state.dangling {
+                    // Caught up: both parses cover the same input.
+                    shortest = Some(witness(&parses));
+                    break;
+                } else if state.dangling.starts_with(token) {
+                    State {
+                        dangling: state.dangling[token.len()..].to_vec(),
+                        len: state.len,
+                        parses,
+                        leader: state.leader
+                    }
+                } else if token.starts_with(&state.dangling) {
+                    // The follower overtakes the leader.
+                    State {
+                        dangling: token[state.dangling.len()..].to_vec(),
+                        len: state.len + token.len() - state.dangling.len(),
+                        parses,
+                        leader: follower
+                    }
+                } else {
+                    continue;
+                };
+
+                queue.push(Reverse((next.len, states.len())));
+                states.push(next);
+            }
+        }
+
+        match shortest {
+            Some(ambiguity) => Decodability::Ambiguous(ambiguity),
+            None => Decodability::Unique
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Decoding with part of the output already known.
+//!
+//! The search runs over pairs of a position in the input and a length
+//! of output produced so far. Knowing which pairs can still reach the
+//! end with an acceptable output, an edge is only followed if it leads
+//! to one of them, so no time is spent on paths that end up rejected.
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use std::cmp;
+use std::hash::Hash;
+use std::ops::RangeInclusive;
+
+use super::{wildcard_pattern, Lattice, Parser, Symbols};
+
+/// Known parts of an output: symbol `i` of the output must be
+/// `slots[i]` unless that is `None`, and the length of the output must
+/// lie in `lengths`. Symbols past the end of `slots` can be anything.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputPattern<O = char> {
+    pub slots: Vec<Option<O>>,
+    pub lengths: RangeInclusive<usize>
+}
+
+impl<O> OutputPattern<O> {
+    /// Outputs of exactly the length of `slots`.
+    pub fn new(slots: Vec<Option<O>>) -> OutputPattern<O> {
+        let len = slots.len();
+        OutputPattern { slots, lengths: len ..= len }
+    }
+}
+
+impl OutputPattern {
+    /// Words of the length of `pattern`, where `wildcard` stands for
+    /// any char, such as `"L?D"`.
+    pub fn wildcard(pattern: &str, wildcard: char) -> OutputPattern {
+        OutputPattern::new(wildcard_pattern(pattern, wildcard))
+    }
+}
+
+/// Lattice with, for each position and length of output so far, a
+/// summary of the acceptable ways to finish.
+struct Constrained<'a, 'p, I: 'a, O: 'a + 'p, T> {
+    lattice: Lattice<'a, I, O>,
+    pattern: &'p OutputPattern<O>,
+
+    /// Longest output worth considering.
+    max_len: usize,
+
+    /// Whether `max_len` stands for itself and every longer length,
+    /// when the pattern says nothing more about those.
+    open: bool,
+
+    /// `finish[position][len]`.
+    finish: Vec<Vec<T>>
+}
+
+impl<'a, 'p, I, O: PartialEq, T: Clone> Constrained<'a, 'p, I, O, T> {
+    /// The summary is built from `zero` for no way to finish, `one`
+    /// for finishing right here, and `add`, as in `suffix_counts`.
+    fn new(lattice: Lattice<'a, I, O>,
+           pattern: &'p OutputPattern<O>,
+           zero: T,
+           one: T,
+           add: impl Fn(T, &T) -> T) -> Constrained<'a, 'p, I, O, T> {
+        let longest_output = lattice.edges()
+            .iter()
+            .map(|edge| edge.output.len())
+            .max()
+            .unwrap_or(0);
+        // Past the last slot and the shortest length allowed, the exact
+        // length no longer matters if no output can be too long.
+        let longest = lattice.len() * longest_output;
+        let open = *pattern.lengths.end() >= longest;
+        let max_len = if open {
+            cmp::min(cmp::max(pattern.slots.len(), *pattern.lengths.start()), longest)
+        } else {
+            *pattern.lengths.end()
+        };
+
+        let mut finish = vec![vec![zero; max_len + 1]; lattice.len() + 1];
+        for (len, end) in finish[lattice.len()].iter_mut().enumerate() {
+            if pattern.lengths.contains(&len) {
+                *end = one.clone();
+            }
+        }
+
+        let mut constrained = Constrained { lattice, pattern, max_len, open, finish };
+        for edge in constrained.lattice.edges().iter().rev() {
+            for len in 0 ..= max_len {
+                if constrained.fits(len, edge.output) {
+                    let next_len = constrained.next_len(len, edge.output);
+                    let total = constrained.finish[edge.start][len].clone();
+                    constrained.finish[edge.start][len] =
+                        add(total, &constrained.finish[edge.end][next_len]);
+                }
+            }
+        }
+        constrained
+    }
+
+    /// Whether `output` can follow `len` symbols of output.
+    fn fits(&self, len: usize, output: &[O]) -> bool {
+        (self.open || len + output.len() <= self.max_len) &&
+            output
+            .iter()
+            .zip(len ..)
+            .all(|(symbol, i)| match self.pattern.slots.get(i) {
+                Some(Some(slot)) => slot == symbol,
+                _ => true
+            })
+    }
+
+    /// Length of output after `output` follows `len` symbols.
+    fn next_len(&self, len: usize, output: &[O]) -> usize {
+        cmp::min(len + output.len(), self.max_len)
+    }
+}
+
+impl<'a, 'p, I, O: PartialEq + Clone> Constrained<'a, 'p, I, O, bool> {
+    /// Every acceptable output, in the order of `decode`.
+    /// Only edges to positions from which some acceptable way to
+    /// finish remains are taken, so the search never backtracks out of
+    /// a dead end.
+    fn outputs(&self) -> Vec<Vec<O>> {
+        let mut outputs = Vec::new();
+        if !self.finish[0][0] {
+            return outputs;
+        }
+
+        // Position, length of output as in `finish`, actual length of
+        // output, and index of the next edge to try.
+        let mut stack = vec![(0, 0, 0, 0)];
+        let mut output = Vec::new();
+        while let Some(&(position, len, _, next)) = stack.last() {
+            if position == self.lattice.len() {
+                outputs.push(output.clone());
+                backtrack(&mut stack, &mut output);
+                continue;
+            }
+
+            let edges = self.lattice.edges_from(position);
+            let found = (next .. edges.len()).find(|&i| {
+                let edge = &edges[i];
+                self.fits(len, edge.output) &&
+                    self.finish[edge.end][self.next_len(len, edge.output)]
+            });
+            match found {
+                Some(i) => {
+                    let edge = &edges[i];
+                    // Resume after this edge when we come back.
+                    stack.last_mut().unwrap().3 = i + 1;
+                    output.extend(edge.output.iter().cloned());
+                    stack.push((edge.end, self.next_len(len, edge.output), output.len(), 0));
+                }
+                None => backtrack(&mut stack, &mut output)
+            }
+        }
+        outputs
+    }
+}
+
+/// Leave the top of the stack, undoing the output that led to it.
+fn backtrack<O>(stack: &mut Vec<(usize, usize, usize, usize)>, output: &mut Vec<O>) {
+    stack.pop();
+    output.truncate(stack.last().map_or(0, |&(_, _, output_len, _)| output_len));
+}
+
+impl<I: Eq + Hash + Clone, O: PartialEq + Clone> Parser<I, O> {
+    /// Like `decode`, keeping only the outputs matching `pattern`.
+    pub fn decode_constrained<S: Symbols<I> + ?Sized>(&self,
+                                                      input: &S,
+                                                      pattern: &OutputPattern<O>)
+                                                      -> Vec<Vec<O>> {
+        Constrained::new(self.lattice(input), pattern, false, true, |found, &more| found || more)
+            .outputs()
+    }
+
+    /// Number of results of `decode_constrained`.
+    pub fn count_constrained<S: Symbols<I> + ?Sized>(&self,
+                                                     input: &S,
+                                                     pattern: &OutputPattern<O>) -> BigUint {
+        Constrained::new(self.lattice(input), pattern, BigUint::zero(), BigUint::one(),
+                         |total, n| total + n)
+            .finish[0][0]
+            .clone()
+    }
+}
+
+impl<I: Eq + Hash + Clone> Parser<I, char> {
+    /// Like `parse`, keeping only the words matching `pattern`.
+    pub fn parse_constrained<S: Symbols<I> + ?Sized>(&self,
+                                                     digits: &S,
+                                                     pattern: &OutputPattern) -> Vec<String> {
+        self.decode_constrained(digits, pattern)
+            .into_iter()
+            .map(|word| word.into_iter().collect())
+            .collect()
+    }
+}
@@ -0,0 +1,241 @@
+//! Decoding into words of a dictionary.
+//!
+//! The search walks the lattice and the dictionary's trie together,
+//! so a path is abandoned as soon as its output stops being a prefix
+//! of some word. Positions and sets of trie nodes from which no word
+//! can be finished are remembered, so each is only explored once.
+
+use std::collections::HashSet;
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::Path;
+
+use super::trie::Trie;
+use super::{Lattice, Parser, Symbols};
+
+/// Set of words, each a sequence of output symbols.
+pub struct Dictionary<O = char> {
+    trie: Trie<O, ()>
+}
+
+impl<O: Eq + Hash> Dictionary<O> {
+    pub fn new() -> Dictionary<O> {
+        Dictionary { trie: Trie::new() }
+    }
+
+    /// The empty word is ignored.
+    pub fn insert(&mut self, word: impl IntoIterator<Item = O>) {
+        let mut word = word.into_iter().peekable();
+        if word.peek().is_some() {
+            self.trie.insert(word, ());
+        }
+    }
+
+    pub fn contains(&self, word: &[O]) -> bool {
+        word.iter()
+            .try_fold(self.trie.root(), |node, symbol| self.trie.child(node, symbol))
+            .and_then(|node| self.trie.value(node))
+            .is_some()
+    }
+
+    /// Every way to read the output of a path of `lattice` as a word,
+    /// or if `sequences`, as a sequence of words. Each path is
+    /// followed once, with all the ways to split its output so far,
+    /// so that its results come together, in the order of `walk`.
+    fn decode<I>(&self, lattice: &Lattice<'_, I, O>, sequences: bool) -> Vec<Vec<Vec<O>>>
+        where O: Clone
+    {
+        let mut found = Vec::new();
+        let mut dead = HashSet::new();
+        let mut stack = vec![Frame {
+            position: 0,
+            splits: vec![(self.trie.root(), Vec::new())],
+            next: 0,
+            found: 0,
+            output_len: 0
+        }];
+        let mut output = Vec::new();
+
+        while let Some(frame) = stack.last_mut() {
+            if frame.position == lattice.len() {
+                for (node, breaks) in &frame.splits {
+                    if self.trie.value(*node).is_some() {
+                        found.push(split(&output, breaks));
+                    }
+                }
+            }
+
+            match lattice.edges_from(frame.position).get(frame.next) {
+                Some(edge) => {
+                    frame.next += 1;
+                    let splits = frame.splits
+                        .iter()
+                        .flat_map(|(node, breaks)| {
+                            self.walk(*node, breaks, edge.output, output.len(), sequences)
+                        })
+                        .collect::<Vec<_>>();
+                    if !splits.is_empty() && !dead.contains(&key(edge.end, &splits)) {
+                        output.extend(edge.output.iter().cloned());
+                        stack.push(Frame {
+                            position: edge.end,
+                            splits,
+                            next: 0,
+                            found: found.len(),
+                            output_len: output.len()
+                        });
+                    }
+                }
+                None => {
+                    let frame = stack.pop().unwrap();
+                    if found.len() == frame.found {
+                        dead.insert(key(frame.position, &frame.splits));
+                    }
+                    output.truncate(stack.last().map_or(0, |parent| parent.output_len));
+                }
+            }
+        }
+        found
+    }
+
+    /// Ways to go on reading `output` after reaching `node` with
+    /// `breaks`, continuing the current word before breaking it off.
+    /// Breaks count from `offset`.
+    fn walk(&self, node: usize, breaks: &[usize], output: &[O], offset: usize, sequences: bool)
+            -> Vec<(usize, Vec<usize>)> {
+        let mut splits = vec![(node, breaks.to_vec())];
+        for (i, symbol) in output.iter().enumerate() {
+            let mut next = Vec::new();
+            for (node, breaks) in splits {
+                if let Some(child) = self.trie.child(node, symbol) {
+                    next.push((child, breaks.clone()));
+                }
+                if sequences && self.trie.value(node).is_some() {
+                    if let Some(child) = self.trie.child(self.trie.root(), symbol) {
+                        let mut breaks = breaks;
+                        breaks.push(offset + i);
+                        next.push((child, breaks));
+                    }
+                }
+            }
+            splits = next;
+        }
+        splits
+    }
+}
+
+impl Dictionary {
+    /// Words as given, so matching is case-sensitive.
+    pub fn from_words<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> Dictionary {
+        let mut dictionary = Dictionary::new();
+        for word in words {
+            dictionary.insert(word.as_ref().chars());
+        }
+        dictionary
+    }
+
+    /// One word per line. Surrounding whitespace and blank lines are
+    /// ignored.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Dictionary> {
+        let text = fs::read_to_string(path)?;
+        Ok(Dictionary::from_words(text.lines().map(str::trim)))
+    }
+}
+
+impl<O: Eq + Hash> Default for Dictionary<O> {
+    fn default() -> Dictionary<O> {
+        Dictionary::new()
+    }
+}
+
+/// Position in the search, at `position` in the lattice with the
+/// output read so far.
+struct Frame {
+    position: usize,
+
+    /// Each way to split the output into words so far, as the node of
+    /// the trie reached by the last word and where the words before it
+    /// end.
+    splits: Vec<(usize, Vec<usize>)>,
+
+    /// Index of the next edge to try.
+    next: usize,
+
+    /// Number of results found before reaching this frame.
+    found: usize,
+
+    /// Length of the output so far.
+    output_len: usize
+}
+
+/// What the rest of the search from a frame depends on.
+fn key(position: usize, splits: &[(usize, Vec<usize>)]) -> (usize, Vec<usize>) {
+    let mut nodes = splits.iter().map(|&(node, _)| node).collect::<Vec<_>>();
+    nodes.sort_unstable();
+    nodes.dedup();
+    (position, nodes)
+}
+
+fn split<O: Clone>(output: &[O], breaks: &[usize]) -> Vec<Vec<O>> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    for &end in breaks.iter().chain(Some(&output.len())) {
+        words.push(output[start .. end].to_vec());
+        start = end;
+    }
+    words
+}
+
+impl<I: Eq + Hash + Clone, O: Eq + Hash + Clone> Parser<I, O> {
+    /// Like `decode`, keeping only the outputs that are words of
+    /// `dictionary`.
+    pub fn decode_words<S: Symbols<I> + ?Sized>(&self,
+                                                input: &S,
+                                                dictionary: &Dictionary<O>) -> Vec<Vec<O>> {
+        dictionary
+            .decode(&self.lattice(input), false)
+            .into_iter()
+            .map(|mut words| words.remove(0))
+            .collect()
+    }
+
+    /// Every way to decode `input` into a sequence of words of
+    /// `dictionary`, in the order of `decode`. An output may split
+    /// into words in several ways, which come together, longest first
+    /// word first, then longest second word first, and so on.
+    pub fn decode_word_sequences<S: Symbols<I> + ?Sized>(&self,
+                                                         input: &S,
+                                                         dictionary: &Dictionary<O>)
+                                                         -> Vec<Vec<Vec<O>>> {
+        dictionary.decode(&self.lattice(input), true)
+    }
+}
+
+impl<I: Eq + Hash + Clone> Parser<I, char> {
+    /// Like `parse`, keeping only the words of `dictionary`.
+    pub fn parse_words<S: Symbols<I> + ?Sized>(&self,
+                                               digits: &S,
+                                               dictionary: &Dictionary) -> Vec<String> {
+        self.decode_words(digits, dictionary)
+            .into_iter()
+            .map(|word| word.into_iter().collect())
+            .collect()
+    }
+
+    /// Like `parse`, reading each word as a sequence of words of
+    /// `dictionary`.
+    pub fn parse_word_sequences<S: Symbols<I> + ?Sized>(&self,
+                                                        digits: &S,
+                                                        dictionary: &Dictionary)
+                                                        -> Vec<Vec<String>> {
+        self.decode_word_sequences(digits, dictionary)
+            .into_iter()
+            .map(|words| {
+                words
+                    .into_iter()
+                    .map(|word| word.into_iter().collect())
+                    .collect()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,381 @@
+//! Decoding lattice: a node for each position in the input, and an
+//! edge for each token of the table matched between two positions.
+//!
+//! Every way to parse the input is a path from the first position to
+//! the last, so the parses of a suffix are shared by all the paths
+//! reaching it instead of being recomputed for each of them.
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use std::cmp::Ordering;
+use std::hash::Hash;
+
+use super::{Entry, Order, Parser};
+
+/// `token` of the table matched from `start` to `end`, producing `output`
+/// with log-probability `weight`.
+#[derive(Debug)]
+pub struct Edge<'a, I: 'a = char, O: 'a = char> {
+    pub start: usize,
+    pub end: usize,
+    pub token: &'a [I],
+    pub output: &'a [O],
+    pub weight: f64
+}
+
+// Not derived, which would require `I: Copy` and `O: Copy`.
+impl<'a, I, O> Clone for Edge<'a, I, O> {
+    fn clone(&self) -> Edge<'a, I, O> {
+        *self
+    }
+}
+
+impl<'a, I, O> Copy for Edge<'a, I, O> {}
+
+// Weights compare bit for bit, so that equality stays an equivalence
+// even for NaN.
+impl<'a, I: PartialEq, O: PartialEq> PartialEq for Edge<'a, I, O> {
+    fn eq(&self, other: &Edge<'a, I, O>) -> bool {
+        self.start == other.start &&
+            self.end == other.end &&
+            self.token == other.token &&
+            self.output == other.output &&
+            self.weight.to_bits() == other.weight.to_bits()
+    }
+}
+
+impl<'a, I: Eq, O: Eq> Eq for Edge<'a, I, O> {}
+
+/// Positions are indices into the input, from `0` to `len()`
+/// inclusive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lattice<'a, I: 'a = char, O: 'a = char> {
+    /// Number of symbols in the input.
+    len: usize,
+
+    /// Edges sorted by start, then in the `Order` of the parser: by
+    /// default, by increasing length, then by order of outputs in the
+    /// config.
+    edges: Vec<Edge<'a, I, O>>,
+
+    /// Edges starting at position i are edges[offsets[i] .. offsets[i + 1]].
+    offsets: Vec<usize>
+}
+
+impl<'a, I: Eq + Hash, O> Lattice<'a, I, O> {
+    /// Match every token at every position, with an edge for each of
+    /// its outputs.
+    pub(crate) fn matching(parser: &'a Parser<I, O>, ds: &[I]) -> Lattice<'a, I, O> {
+        let mut edges = Vec::new();
+        for start in 0 .. ds.len() {
+            for (lookahead_index, entry) in parser.trie.prefixes(&ds[start..]) {
+                Lattice::push_edges(&mut edges, start, start + lookahead_index, entry);
+            }
+        }
+        Lattice::ordered(parser, ds.len(), edges)
+    }
+
+    /// Match every token at every position of a pattern, where `None`
+    /// stands for any symbol. Several tokens may then match the same
+    /// span; their edges are in order of the table.
+    pub(crate) fn matching_pattern(parser: &'a Parser<I, O>, pattern: &[Option<I>])
+                                   -> Lattice<'a, I, O> {
+        let trie = &parser.trie;
+        let mut edges = Vec::new();
+        for start in 0 .. pattern.len() {
+            let mut found = Vec::new();
+            let mut stack = vec![(trie.root(), start)];
+            while let Some((node, end)) = stack.pop() {
+                if end > start {
+                    if let Some(entry) = trie.value(node) {
+                        found.push((end, trie.rank(node), entry));
+                    }
+                }
+                match pattern.get(end) {
+                    Some(Some(symbol)) =>
+                        stack.extend(trie.child(node, symbol).map(|child| (child, end + 1))),
+                    Some(None) =>
+                        stack.extend(trie.children(node).map(|child| (child, end + 1))),
+                    None => ()
+                }
+            }
+
+            found.sort_by_key(|&(end, rank, _)| (end, rank));
+            for (end, _, entry) in found {
+                Lattice::push_edges(&mut edges, start, end, entry);
+            }
+        }
+        Lattice::ordered(parser, pattern.len(), edges)
+    }
+
+    /// For a prefix-free table: at most one token matches at each
+    /// position, so follow the only path from the start, as far as it
+    /// goes. Positions off that path are never reachable anyway.
+    pub(crate) fn greedy(parser: &'a Parser<I, O>, ds: &[I]) -> Lattice<'a, I, O> {
+        let mut edges = Vec::new();
+        let mut start = 0;
+        while let Some((lookahead_index, entry)) = parser.trie
+            .prefixes(&ds[start..])
+            .find(|(_, entry)| !entry.outputs.is_empty())
+        {
+            let end = start + lookahead_index;
+            Lattice::push_edges(&mut edges, start, end, entry);
+            start = end;
+        }
+        Lattice::ordered(parser, ds.len(), edges)
+    }
+
+    /// Edges found shortest token first, reordered as `parser` lists
+    /// its parses.
+    fn ordered(parser: &'a Parser<I, O>, len: usize, mut edges: Vec<Edge<'a, I, O>>)
+               -> Lattice<'a, I, O> {
+        let longest_first = parser.order == Order::LongestTokenFirst;
+        if longest_first || parser.compare_outputs.is_some() {
+            // Stable, so ties stay in the order of the config.
+            edges.sort_by(|a, b| {
+                a.start
+                    .cmp(&b.start)
+                    .then_with(|| match parser.compare_outputs {
+                        Some(compare) => compare(a.output, b.output),
+                        None => Ordering::Equal
+                    })
+                    .then_with(|| if longest_first { b.end.cmp(&a.end) } else { a.end.cmp(&b.end) })
+            });
+        }
+        Lattice::from_edges(len, edges)
+    }
+
+    fn push_edges(edges: &mut Vec<Edge<'a, I, O>>,
+                  start: usize,
+                  end: usize,
+                  entry: &'a Entry<I, O>) {
+        for (i, output) in entry.outputs.iter().enumerate() {
+            let weight = entry.weights.as_ref().map_or(0.0, |weights| weights[i]);
+            edges.push(Edge { start, end, token: &entry.token, output, weight });
+        }
+    }
+}
+
+impl<'a, I, O> Lattice<'a, I, O> {
+    /// Only the edges lying on a path from the start to the end.
+    pub fn pruned(&self) -> Lattice<'a, I, O> {
+        let reachable = self.reachable();
+        let live = self.live();
+        let edges = self.edges
+            .iter()
+            .filter(|edge| reachable[edge.start] && live[edge.end])
+            .cloned()
+            .collect();
+        Lattice::from_edges(self.len, edges)
+    }
+
+    /// For each position, whether it can be reached from the start.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.len + 1];
+        reachable[0] = true;
+        for edge in &self.edges {
+            reachable[edge.end] |= reachable[edge.start];
+        }
+        reachable
+    }
+
+    /// For each position, whether the end can be reached from it.
+    pub fn live(&self) -> Vec<bool> {
+        let mut live = vec![false; self.len + 1];
+        live[self.len] = true;
+        for edge in self.edges.iter().rev() {
+            live[edge.start] |= live[edge.end];
+        }
+        live
+    }
+
+    fn from_edges(len: usize, edges: Vec<Edge<'a, I, O>>) -> Lattice<'a, I, O> {
+        let mut offsets = vec![0; len + 2];
+        for edge in &edges {
+            offsets[edge.start + 1] += 1;
+        }
+        for i in 1 .. offsets.len() {
+            offsets[i] += offsets[i - 1];
+        }
+
+        Lattice { len, edges, offsets }
+    }
+
+    /// Number of symbols in the input, which is also the last position.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the input is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether there is at least one path from the start to the end.
+    pub fn is_decodable(&self) -> bool {
+        self.live()[0]
+    }
+
+    /// All edges, sorted by start, then in the `Order` of the parser.
+    pub fn edges(&self) -> &[Edge<'a, I, O>] {
+        &self.edges
+    }
+
+    /// Edges starting at `position`, in the `Order` of the parser.
+    pub fn edges_from(&self, position: usize) -> &[Edge<'a, I, O>] {
+        &self.edges[self.offsets[position] .. self.offsets[position + 1]]
+    }
+
+    /// Edges ending at `position`, sorted by start.
+    pub fn edges_to<'b>(&'b self, position: usize)
+                        -> impl Iterator<Item = &'b Edge<'a, I, O>> + 'b {
+        self.edges.iter().filter(move |edge| edge.end == position)
+    }
+
+    /// Positions where at least one edge starts or ends.
+    pub fn positions(&self) -> Vec<usize> {
+        let mut touched = vec![false; self.len + 1];
+        for edge in &self.edges {
+            touched[edge.start] = true;
+            touched[edge.end] = true;
+        }
+        (0 ..= self.len).filter(|&i| touched[i]).collect()
+    }
+
+    /// Number of paths from the start to the end.
+    pub fn count(&self) -> BigUint {
+        self.suffix_counts(BigUint::zero(), BigUint::one(), |total, n| total + n)
+            .swap_remove(0)
+    }
+
+    /// Path from the start to the end with the highest total weight,
+    /// with that weight, or `None` if there is no path. Among equally
+    /// good paths, the first in the order of `Paths`.
+    pub fn best_path(&self) -> Option<(Vec<Edge<'a, I, O>>, f64)> {
+        // Best path from each position to the end, as its score and
+        // first edge, if there is any path. Paths of weight
+        // `f64::NEG_INFINITY` still count.
+        let mut best = vec![None; self.len + 1];
+        best[self.len] = Some((0.0, None));
+        for edge in self.edges.iter().rev() {
+            if let Some((rest, _)) = best[edge.end] {
+                let score = edge.weight + rest;
+                match best[edge.start] {
+                    Some((other, _)) if other > score => {}
+                    _ => best[edge.start] = Some((score, Some(*edge)))
+                }
+            }
+        }
+
+        let (score, _) = best[0]?;
+        let mut path = Vec::new();
+        let mut position = 0;
+        while let Some((_, Some(edge))) = best[position] {
+            path.push(edge);
+            position = edge.end;
+        }
+        Some((path, score))
+    }
+
+    /// Log of the sum over all paths of the exponential of their total
+    /// weight, `f64::NEG_INFINITY` if there are no paths.
+    pub fn log_total(&self) -> f64 {
+        self.suffix_log_totals()[0]
+    }
+
+    /// For each position, `log_total` of the paths from it to the end.
+    pub(crate) fn suffix_log_totals(&self) -> Vec<f64> {
+        let mut totals = vec![f64::NEG_INFINITY; self.len + 1];
+        totals[self.len] = 0.0;
+        for edge in self.edges.iter().rev() {
+            totals[edge.start] = log_add(totals[edge.start], edge.weight + totals[edge.end]);
+        }
+        totals
+    }
+
+    /// For each position, the number of paths from it to the end.
+    /// The number representation is supplied by the caller.
+    pub(crate) fn suffix_counts<T: Clone>(&self,
+                                   zero: T,
+                                   one: T,
+                                   add: impl Fn(T, &T) -> T) -> Vec<T> {
+        let mut counts = vec![zero; self.len + 1];
+        counts[self.len] = one;
+        for edge in self.edges.iter().rev() {
+            let total = counts[edge.start].clone();
+            counts[edge.start] = add(total, &counts[edge.end]);
+        }
+        counts
+    }
+}
+
+/// `ln(exp(a) + exp(b))`, without overflow.
+fn log_add(a: f64, b: f64) -> f64 {
+    let (high, low) = if a > b { (a, b) } else { (b, a) };
+    if low == f64::NEG_INFINITY {
+        high
+    } else {
+        high + (low - high).exp().ln_1p()
+    }
+}
+
+/// Depth-first search of a pruned lattice with an explicit stack,
+/// yielding each path from the start to the end.
+/// Every edge leads to the end, so each path is found in time linear
+/// in the length of the input.
+pub(crate) struct Paths<'a, I: 'a, O: 'a> {
+    lattice: Lattice<'a, I, O>,
+
+    /// Position in the input, and index of the next edge to try from it.
+    stack: Vec<(usize, usize)>,
+
+    /// Edges leading to the top of the stack.
+    path: Vec<Edge<'a, I, O>>,
+
+    /// Whether the path was returned by the previous call.
+    found: bool
+}
+
+impl<'a, I, O> Paths<'a, I, O> {
+    pub fn new(lattice: Lattice<'a, I, O>) -> Paths<'a, I, O> {
+        let stack = if lattice.is_decodable() { vec![(0, 0)] } else { vec![] };
+        Paths {
+            lattice,
+            stack,
+            path: Vec::new(),
+            found: false
+        }
+    }
+
+    /// Not an `Iterator`, to lend out the path without copying it.
+    pub fn next_path(&mut self) -> Option<&[Edge<'a, I, O>]> {
+        if self.found {
+            self.found = false;
+            self.backtrack();
+        }
+
+        while let Some(&(position, next)) = self.stack.last() {
+            if position == self.lattice.len {
+                self.found = true;
+                return Some(&self.path);
+            }
+
+            match self.lattice.edges_from(position).get(next).cloned() {
+                Some(edge) => {
+                    // Resume after this edge when we come back.
+                    self.stack.last_mut().unwrap().1 = next + 1;
+                    self.stack.push((edge.end, 0));
+                    self.path.push(edge);
+                }
+                None => self.backtrack()
+            }
+        }
+        None
+    }
+
+    /// Leave the current position, undoing the edge that led to it.
+    fn backtrack(&mut self) {
+        self.stack.pop();
+        self.path.pop();
+    }
+}
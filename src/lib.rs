@@ -1,13 +1,40 @@
 //! Solve a [number word problem](http://programmingpraxis.com/2014/07/25/number-words/).
 
-use std::cmp;
-use std::collections::HashMap;
-use std::collections::VecDeque;
+extern crate num_bigint;
+extern crate num_traits;
+extern crate rand;
 
-pub type Config = Vec<(String, char)>;
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+mod analysis;
+mod constrained;
+mod dictionary;
+mod lattice;
+mod ngram;
+mod rank;
+mod sample;
+mod search;
+mod trie;
+mod validate;
+
+pub use analysis::{Ambiguity, Decodability};
+pub use constrained::OutputPattern;
+pub use dictionary::Dictionary;
+pub use lattice::{Edge, Lattice};
+pub use ngram::NgramModel;
+use lattice::Paths;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::Range;
+use trie::Trie;
+pub use validate::{validate_config, Diagnostic};
 
-/// Word in progress, constructed back to front.
-type WordInProgress = VecDeque<char>;
+/// Each token produces one char.
+pub type Config = Vec<(String, char)>;
 
 pub fn default_config() -> Config {
     (b'A' ..= b'Z')
@@ -17,107 +44,1275 @@ pub fn default_config() -> Config {
         .collect()
 }
 
-pub struct Parser {
-    max_lookahead: usize,
-    table: HashMap<Vec<char>, char>
+/// Each token may produce any of several chars, so that every
+/// combination is a possible word.
+pub type MultiConfig = Vec<(String, Vec<char>)>;
+
+/// Letters on a phone keypad.
+pub fn keypad_config() -> MultiConfig {
+    ["ABC", "DEF", "GHI", "JKL", "MNO", "PQRS", "TUV", "WXYZ"]
+        .iter()
+        .enumerate()
+        .map(|(i, letters)|
+             ((i + 2).to_string(),
+              letters.chars().collect()))
+        .collect()
+}
+
+/// Most general form: each token may produce any of several strings,
+/// such as `"27"` producing `"TH"`.
+pub type StringConfig = Vec<(String, Vec<String>)>;
+
+/// Generic form of `StringConfig`: each token, a sequence of input
+/// symbols, may produce any of several sequences of output symbols.
+pub type Table<I, O> = Vec<(Vec<I>, Vec<Vec<O>>)>;
+
+/// Each token produces one char, with a log-probability, such as
+/// `("5", 'E', -0.5)`.
+pub type WeightedConfig = Vec<(String, char, f64)>;
+
+/// Generic form of `WeightedConfig`, like `Table` with a
+/// log-probability for each output.
+pub type WeightedTable<I, O> = Vec<(Vec<I>, Vec<(Vec<O>, f64)>)>;
+
+/// Input to a parser: a sequence of symbols, or a string read as its
+/// chars.
+pub trait Symbols<I: Clone> {
+    fn symbols(&self) -> Cow<'_, [I]>;
+}
+
+impl Symbols<char> for str {
+    fn symbols(&self) -> Cow<'_, [char]> {
+        Cow::Owned(self.chars().collect())
+    }
+}
+
+impl Symbols<char> for String {
+    fn symbols(&self) -> Cow<'_, [char]> {
+        self.as_str().symbols()
+    }
+}
+
+impl<I: Clone> Symbols<I> for [I] {
+    fn symbols(&self) -> Cow<'_, [I]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<I: Clone> Symbols<I> for Vec<I> {
+    fn symbols(&self) -> Cow<'_, [I]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<I: Clone, const N: usize> Symbols<I> for [I; N] {
+    fn symbols(&self) -> Cow<'_, [I]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl<I: Clone, S: Symbols<I> + ?Sized> Symbols<I> for &S {
+    fn symbols(&self) -> Cow<'_, [I]> {
+        (**self).symbols()
+    }
+}
+
+/// Decodes sequences of `I` into sequences of `O`.
+/// By default, strings into strings.
+pub struct Parser<I = char, O = char> {
+    trie: Trie<I, Entry<I, O>>,
+
+    /// No token is a prefix of another, so parse greedily.
+    prefix_free: bool,
+
+    order: Order,
+
+    /// Set by `by_output`, which needs `O: Ord`.
+    compare_outputs: Option<Compare<O>>
+}
+
+type Compare<O> = fn(&[O], &[O]) -> Ordering;
+
+/// Order in which parses are listed, by `parse` and everything else
+/// enumerating them. Each is the order of a depth-first search that
+/// tries the tokens matching at each position in the given order; the
+/// outputs of a token stay in the order of the config, unless the
+/// parser sorts them `by_output`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Order {
+    /// Shorter tokens first, so that `"1234"` gives `ABCD`, `AWD`,
+    /// `LCD`.
+    #[default]
+    ShortestTokenFirst,
+
+    /// Longer tokens first, so that `"1234"` gives `LCD`, `AWD`,
+    /// `ABCD`.
+    LongestTokenFirst
+}
+
+/// Token of the table, with its outputs.
+struct Entry<I, O> {
+    token: Vec<I>,
+    outputs: Vec<Vec<O>>,
+
+    /// Log-probability of each output, if the table is weighted.
+    /// Otherwise every weight is `0.0`.
+    weights: Option<Vec<f64>>
 }
 
 impl Parser {
+    /// If a token appears more than once, the last entry wins.
     pub fn new(config: &Config) -> Parser {
-        Parser {
-            max_lookahead: config
-                .iter()
-                .map(|&(ref s, _)|
-                     s.len())   // get string lengths
-                .fold(0, cmp::max),
-            table: config
-                .iter()
-                .map(|&(ref s, c)| // String -> Vec<char>
-                     (s.chars().collect(), c))
-                .collect()
+        let mut trie = Trie::new();
+        for &(ref s, c) in config {
+            trie.insert(s.chars(), Entry {
+                token: s.chars().collect(),
+                outputs: vec![vec![c]],
+                weights: None
+            });
         }
+        Parser::from_trie(trie)
     }
 
-    /// Entry point.
-    /// Internally, get out of string early, to use chars instead.
-    /// Note the use of into_iter.
-    pub fn parse(&self, digits: &str) -> Vec<String> {
-        // It is convenient to use char slices.
-        let v = digits.chars().collect::<Vec<char>>();
-        let parsed = self.parse_list(&v[..]);
-        parsed
+    /// If a token appears more than once, its outputs are combined.
+    pub fn from_multi(config: &MultiConfig) -> Parser {
+        let table = config
+            .iter()
+            .map(|(s, cs)|
+                 (s.chars().collect(),
+                  cs.iter().map(|&c| vec![c]).collect()))
+            .collect();
+        Parser::from_table(&table)
+    }
+
+    /// If a token appears more than once, its outputs are combined.
+    pub fn from_strings(config: &StringConfig) -> Parser {
+        let table = config
+            .iter()
+            .map(|(s, outputs)|
+                 (s.chars().collect(),
+                  outputs.iter().map(|o| o.chars().collect()).collect()))
+            .collect();
+        Parser::from_table(&table)
+    }
+
+    /// If a token appears more than once, its outputs are combined.
+    pub fn from_weighted(config: &WeightedConfig) -> Parser {
+        let table = config
+            .iter()
+            .map(|&(ref s, c, weight)|
+                 (s.chars().collect(),
+                  vec![(vec![c], weight)]))
+            .collect();
+        Parser::from_weighted_table(&table)
+    }
+
+    /// Like `new`, but refuse a config with any diagnostic from
+    /// `validate_config`.
+    pub fn try_new(config: &Config) -> Result<Parser, Vec<Diagnostic>> {
+        let diagnostics = validate_config(config);
+        if diagnostics.is_empty() {
+            Ok(Parser::new(config))
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    /// Digits that `parse` into `word`.
+    /// If there are several, the first of `encode_all`.
+    pub fn encode(&self, word: &str) -> Result<String, EncodeError> {
+        let ws = word.chars().collect::<Vec<char>>();
+        let inverse = self.inverse();
+        let lattice = inverse
+            .try_lattice(&ws)
+            .map_err(|ParseError { position, character }|
+                     EncodeError { position, character })?;
+        let digits = Words { paths: Paths::new(lattice) }
+            .next()
+            .expect("decodable lattice has a path");
+        Ok(digits)
+    }
+
+    /// Every string of digits that `parse` into `word`.
+    pub fn encode_all(&self, word: &str) -> Vec<String> {
+        self.inverse().parse(word)
+    }
+
+    /// Most probable word, with its log-probability, or `None` if
+    /// there is no parse.
+    pub fn parse_best(&self, digits: &str) -> Option<(String, f64)> {
+        self.decode_best(digits)
+            .map(|(word, score)| (word.into_iter().collect(), score))
+    }
+
+    /// Like `parse`, where `wildcard` in `pattern` stands for any char
+    /// appearing in tokens. Each word comes with the filling of the
+    /// pattern it was parsed from.
+    pub fn parse_pattern(&self, pattern: &str, wildcard: char) -> Vec<PatternMatch> {
+        self.decode_pattern(&wildcard_pattern(pattern, wildcard))
             .into_iter()
-            .map(|char_list| {
-                char_list
-                    .into_iter()
-                    .collect()
+            .map(|(filling, word)| PatternMatch {
+                filling: filling.into_iter().collect(),
+                word: word.into_iter().collect()
             })
             .collect()
     }
 
-    /// Recursive.
-    /// Note the use of flat_map and into_iter to avoid redundant
-    /// allocation and copying of vectors.
-    fn parse_list(&self, ds: &[char]) -> Vec<WordInProgress> {
-        if ds.is_empty() {
-            vec![VecDeque::new()]
+    /// Number of results of `parse_pattern`.
+    pub fn count_pattern(&self, pattern: &str, wildcard: char) -> BigUint {
+        self.pattern_lattice(&wildcard_pattern(pattern, wildcard)).count()
+    }
+
+    /// Like `parse`, but also report how each word was produced.
+    pub fn parse_segmented(&self, digits: &str) -> Vec<Decoding> {
+        let ds = digits.chars().collect::<Vec<char>>();
+        let mut paths = Paths::new(self.reachable_matches(&ds).pruned());
+        let mut decodings = Vec::new();
+        while let Some(path) = paths.next_path() {
+            decodings.push(Decoding {
+                word: path.iter().flat_map(|edge| edge.output).collect(),
+                pieces: path
+                    .iter()
+                    .map(|edge| Piece {
+                        span: edge.start .. edge.end,
+                        token: edge.token.iter().collect(),
+                        output: edge.output.iter().collect()
+                    })
+                    .collect()
+            });
+        }
+        decodings
+    }
+}
+
+impl<I: Eq + Hash + Clone> Parser<I, char> {
+    /// Entry point.
+    /// Collects `iter`, so no recursion is involved and arbitrarily
+    /// long inputs are fine.
+    ///
+    /// Words come in the canonical order given by `order`, by default
+    /// shorter tokens first. `nth` and `rank` index into this order.
+    pub fn parse<S: Symbols<I> + ?Sized>(&self, digits: &S) -> Vec<String> {
+        self.iter(digits).collect()
+    }
+
+    /// Like `parse`, but fail if `digits` has no parse at all.
+    pub fn try_parse<S: Symbols<I> + ?Sized>(&self, digits: &S)
+                                             -> Result<Vec<String>, ParseError<I>> {
+        self.try_decode(digits)
+            .map(|words| words
+                 .into_iter()
+                 .map(|word| word.into_iter().collect())
+                 .collect())
+    }
+
+    /// Lazily enumerate the possible words, in the same order as
+    /// `parse`.
+    /// Only the lattice and the current path are kept, so memory is
+    /// proportional to the length of the input.
+    pub fn iter<S: Symbols<I> + ?Sized>(&self, digits: &S) -> Words<'_, I> {
+        Words { paths: Paths::new(self.lattice(digits)) }
+    }
+}
+
+impl<I: Eq + Hash + Clone, O: Clone> Parser<I, O> {
+    /// If a token appears more than once, its outputs are combined.
+    pub fn from_table(table: &Table<I, O>) -> Parser<I, O> {
+        let mut trie = Trie::new();
+        for (token, outputs) in table {
+            let entry = trie.get_or_insert_with(token.iter().cloned(), || Entry {
+                token: token.clone(),
+                outputs: Vec::new(),
+                weights: None
+            });
+            entry.outputs.extend(outputs.iter().cloned());
+        }
+        Parser::from_trie(trie)
+    }
+
+    /// If a token appears more than once, its outputs are combined.
+    pub fn from_weighted_table(table: &WeightedTable<I, O>) -> Parser<I, O> {
+        let mut trie = Trie::new();
+        for (token, outputs) in table {
+            let entry = trie.get_or_insert_with(token.iter().cloned(), || Entry {
+                token: token.clone(),
+                outputs: Vec::new(),
+                weights: Some(Vec::new())
+            });
+            for (output, weight) in outputs {
+                entry.outputs.push(output.clone());
+                entry.weights.get_or_insert_with(Vec::new).push(*weight);
+            }
+        }
+        Parser::from_trie(trie)
+    }
+
+    fn from_trie(trie: Trie<I, Entry<I, O>>) -> Parser<I, O> {
+        let mut parser = Parser {
+            trie,
+            prefix_free: false,
+            order: Order::default(),
+            compare_outputs: None
+        };
+        parser.prefix_free = parser.is_prefix_free();
+        parser
+    }
+
+    /// The same parser, listing parses in `order` instead.
+    pub fn with_order(mut self, order: Order) -> Parser<I, O> {
+        self.order = order;
+        self
+    }
+
+    pub fn order(&self) -> Order {
+        self.order
+    }
+
+    /// The same parser, trying the outputs matching at each position
+    /// in sorted order, including those of the same token. Equal
+    /// outputs are tried in `order()`. When each output is a single
+    /// char produced by one token only, as in `default_config` and
+    /// `keypad_config`, words come out sorted.
+    pub fn by_output(mut self) -> Parser<I, O>
+        where O: Ord
+    {
+        self.compare_outputs = Some(|a, b| a.cmp(b));
+        self
+    }
+
+    /// Whether the parser was made `by_output`.
+    pub fn is_by_output(&self) -> bool {
+        self.compare_outputs.is_some()
+    }
+
+    /// Generic `parse`: every possible output sequence.
+    pub fn decode<S: Symbols<I> + ?Sized>(&self, input: &S) -> Vec<Vec<O>> {
+        self.decode_iter(input).collect()
+    }
+
+    /// Generic `try_parse`.
+    pub fn try_decode<S: Symbols<I> + ?Sized>(&self, input: &S)
+                                              -> Result<Vec<Vec<O>>, ParseError<I>> {
+        let lattice = self.try_lattice(&input.symbols())?;
+        Ok(DecodeIter { paths: Paths::new(lattice) }.collect())
+    }
+
+    /// Pruned lattice, or the reason there is no parse at all.
+    fn try_lattice(&self, ds: &[I]) -> Result<Lattice<'_, I, O>, ParseError<I>> {
+        let matches = self.reachable_matches(ds);
+        let reachable = matches.reachable();
+        if reachable[ds.len()] {
+            return Ok(matches.pruned());
+        }
+
+        // Tokens matched anywhere, not only where parsing gets to.
+        let mut starting = vec![0i64; ds.len() + 1];
+        for edge in Lattice::matching(self, ds).edges() {
+            if edge.start < edge.end {
+                starting[edge.start] += 1;
+                starting[edge.end] -= 1;
+            }
+        }
+        let mut covering = 0;
+        let uncovered = (0 .. ds.len()).find(|&i| {
+            covering += starting[i];
+            covering == 0
+        });
+
+        // Every symbol is covered, but the parse cannot get past the
+        // furthest position reached from the start.
+        let position = uncovered.unwrap_or_else(|| {
+            (0 .. ds.len())
+                .rev()
+                .find(|&i| reachable[i])
+                .unwrap_or(0)
+        });
+        Err(ParseError {
+            position,
+            character: ds[position].clone()
+        })
+    }
+
+    /// Generic `iter`.
+    pub fn decode_iter<S: Symbols<I> + ?Sized>(&self, input: &S) -> DecodeIter<'_, I, O> {
+        DecodeIter { paths: Paths::new(self.lattice(input)) }
+    }
+
+    /// Exact number of possible words, however large, without
+    /// constructing them.
+    pub fn count<S: Symbols<I> + ?Sized>(&self, digits: &S) -> BigUint {
+        self.fold_counts(digits, BigUint::zero(), BigUint::one(),
+                         |total, n| total + n)
+    }
+
+    /// Faster `count`, or `None` on `u128` overflow.
+    pub fn checked_count<S: Symbols<I> + ?Sized>(&self, digits: &S) -> Option<u128> {
+        self.fold_counts(digits, Some(0), Some(1), |total, &n|
+                         total.and_then(|t| n.and_then(|n| t.checked_add(n))))
+    }
+
+    /// Number of possible words, modulo `m`.
+    ///
+    /// Panics if `m` is zero.
+    pub fn count_mod<S: Symbols<I> + ?Sized>(&self, digits: &S, m: u64) -> u64 {
+        assert!(m != 0, "count_mod with zero modulus");
+        self.fold_counts(digits, 0, 1 % m, |total, &n|
+                         ((u128::from(total) + u128::from(n))
+                          % u128::from(m)) as u64)
+    }
+
+    /// Most probable output sequence, with its log-probability: the sum
+    /// of the weights of its tokens. Among equally probable ones, the
+    /// first found. `None` if there is no parse, even one of
+    /// probability 0.
+    pub fn decode_best<S: Symbols<I> + ?Sized>(&self, input: &S) -> Option<(Vec<O>, f64)> {
+        self.lattice(input)
+            .best_path()
+            .map(|(path, score)| {
+                (path.iter().flat_map(|edge| edge.output.iter().cloned()).collect(), score)
+            })
+    }
+
+    /// Log of the total probability of all the parses of `digits`,
+    /// `f64::NEG_INFINITY` if there are none. Unweighted, the log of
+    /// their number.
+    pub fn log_total<S: Symbols<I> + ?Sized>(&self, digits: &S) -> f64 {
+        self.lattice(digits).log_total()
+    }
+
+    /// Every way to parse `digits`, as paths through a lattice.
+    /// Edges not on a path from the start to the end are pruned.
+    pub fn lattice<S: Symbols<I> + ?Sized>(&self, digits: &S) -> Lattice<'_, I, O> {
+        self.reachable_matches(&digits.symbols()).pruned()
+    }
+
+    /// Every token matched anywhere in `digits`, whether or not it
+    /// is part of a parse.
+    pub fn matches<S: Symbols<I> + ?Sized>(&self, digits: &S) -> Lattice<'_, I, O> {
+        Lattice::matching(self, &digits.symbols())
+    }
+
+    /// Parser for the other direction, from outputs back to tokens.
+    /// Empty tokens, and tokens with an empty output, cannot be
+    /// recovered, and are left out. Weights are dropped.
+    pub fn inverse(&self) -> Parser<O, I>
+        where O: Eq + Hash
+    {
+        let table = self.trie
+            .entries()
+            .into_iter()
+            .filter(|(token, _)| !token.is_empty())
+            .flat_map(|(token, entry)|
+                      entry.outputs
+                      .iter()
+                      .map(move |output| (output.clone(), vec![token.clone()])))
+            .collect();
+        Parser::from_table(&table)
+    }
+
+    /// Every way to parse any filling of `pattern`, where `None`
+    /// stands for any symbol appearing in tokens.
+    pub fn pattern_lattice(&self, pattern: &[Option<I>]) -> Lattice<'_, I, O> {
+        Lattice::matching_pattern(self, pattern).pruned()
+    }
+
+    /// Generic `parse_pattern`: each filling of `pattern` with one of
+    /// its output sequences.
+    pub fn decode_pattern(&self, pattern: &[Option<I>]) -> Vec<(Vec<I>, Vec<O>)> {
+        let mut paths = Paths::new(self.pattern_lattice(pattern));
+        let mut decodings = Vec::new();
+        while let Some(path) = paths.next_path() {
+            decodings.push((
+                path.iter().flat_map(|edge| edge.token.iter().cloned()).collect(),
+                path.iter().flat_map(|edge| edge.output.iter().cloned()).collect()
+            ));
+        }
+        decodings
+    }
+
+    /// At least the matches reachable from the start.
+    fn reachable_matches(&self, ds: &[I]) -> Lattice<'_, I, O> {
+        if self.prefix_free {
+            Lattice::greedy(self, ds)
         } else {
-            // Try all parses up to the maximum lookahead.
-            let max_lookahead_index = cmp::min(self.max_lookahead, ds.len());
-            let prefix = &ds[..max_lookahead_index];
-
-            (1 ..= max_lookahead_index)
-                .flat_map(|lookahead_index| {
-                    // Split into possible parsed/unparsed configurations.
-                    let unparsed_index = cmp::min(lookahead_index,
-                                                  max_lookahead_index);
-
-                    // Actual token to look up.
-                    let token_slice = &prefix[..unparsed_index];
-
-                    self.table.get(token_slice).map_or_else(
-                        || vec![],
-                        |&c| {
-                            let unparsed = &ds[unparsed_index..];
-
-                            self.parse_list(unparsed)
-                                .into_iter()
-                                .map(|mut s| {
-                                    // mutate for efficiency
-                                    s.push_front(c);
-                                    s
-                                })
-                                .collect::<Vec<WordInProgress>>()
-                        })
-                        .into_iter()
-                })
-                .collect()
+            Lattice::matching(self, ds)
         }
     }
+
+    fn fold_counts<S: Symbols<I> + ?Sized, T: Clone>(&self,
+                                                     digits: &S,
+                                                     zero: T,
+                                                     one: T,
+                                                     add: impl Fn(T, &T) -> T) -> T {
+        self.lattice(digits)
+            .suffix_counts(zero, one, add)
+            .swap_remove(0)
+    }
+}
+
+/// Iterator over possible words, created by `Parser::iter`.
+pub struct Words<'a, I: 'a = char> {
+    paths: Paths<'a, I, char>
+}
+
+impl<'a, I> Iterator for Words<'a, I> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.paths
+            .next_path()
+            .map(|path| path.iter().flat_map(|edge| edge.output).collect())
+    }
+}
+
+/// Iterator over possible output sequences, created by
+/// `Parser::decode_iter`.
+pub struct DecodeIter<'a, I: 'a, O: 'a> {
+    paths: Paths<'a, I, O>
+}
+
+impl<'a, I, O: Clone> Iterator for DecodeIter<'a, I, O> {
+    type Item = Vec<O>;
+
+    fn next(&mut self) -> Option<Vec<O>> {
+        self.paths
+            .next_path()
+            .map(|path| path
+                 .iter()
+                 .flat_map(|edge| edge.output.iter().cloned())
+                 .collect())
+    }
+}
+
+/// A possible word, with the tokens it was produced from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decoding {
+    pub word: String,
+    pub pieces: Vec<Piece>
+}
+
+/// Token found at `span` (char indices into the input), producing `output`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Piece {
+    pub span: Range<usize>,
+    pub token: String,
+    pub output: String
+}
+
+/// A possible word for some filling of a pattern, returned by
+/// `Parser::parse_pattern`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternMatch {
+    pub filling: String,
+    pub word: String
+}
+
+fn wildcard_pattern(pattern: &str, wildcard: char) -> Vec<Option<char>> {
+    pattern
+        .chars()
+        .map(|c| if c == wildcard { None } else { Some(c) })
+        .collect()
+}
+
+/// Input with no parse, returned by `Parser::try_parse`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError<I = char> {
+    /// Earliest index in the input that no token can cover. If every
+    /// symbol is covered by some token, the furthest index the parse
+    /// can get to but not past.
+    pub position: usize,
+
+    /// The symbol at `position`.
+    pub character: I
+}
+
+impl<I: fmt::Debug> fmt::Display for ParseError<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot parse {:?} at position {}",
+               self.character, self.position)
+    }
 }
 
+impl<I: fmt::Debug> Error for ParseError<I> {}
+
+/// Word with no encoding, returned by `Parser::encode`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodeError {
+    /// Earliest char index in the word that no output can cover, or
+    /// as for `ParseError`, the furthest index an encoding can get to.
+    pub position: usize,
+
+    /// The char at `position`.
+    pub character: char
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot encode {:?} at position {}",
+               self.character, self.position)
+    }
+}
+
+impl Error for EncodeError {}
+
 #[cfg(test)]
 mod test {
-    use super::default_config;
-    use super::Parser;
-    use std::collections::HashSet;
+    use super::{default_config, keypad_config};
+    use super::{Ambiguity, Decodability, Dictionary, NgramModel, OutputPattern};
+    use super::{Decoding, Diagnostic, Edge, EncodeError, Order, ParseError, Parser, PatternMatch,
+                Piece};
+    use num_bigint::BigUint;
+    use num_traits::{One, Zero};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
 
     #[test]
     fn it_works() {
         let parser = Parser::new(&default_config());
 
-        let expected = ["ABCD", "AWD", "LCD"];
-        let expected_set = expected
-            .iter()
-            .cloned()
-            .collect::<HashSet<&str>>();
+        assert_eq!(parser.parse("1234"), vec!["ABCD", "AWD", "LCD"])
+    }
 
-        let actual = parser.parse("1234");
-        let actual_set = actual
+    #[test]
+    fn order() {
+        let parser = Parser::new(&default_config());
+        assert_eq!(parser.order(), Order::ShortestTokenFirst);
+        assert_eq!(parser.parse("11121"),
+                   vec!["AAABA", "AAAU", "AALA", "AKBA", "AKU", "KABA", "KAU", "KLA"]);
+
+        let parser = parser.with_order(Order::LongestTokenFirst);
+        assert_eq!(parser.parse("11121"),
+                   vec!["KLA", "KAU", "KABA", "AKU", "AKBA", "AALA", "AAAU", "AAABA"]);
+        assert_eq!(parser.nth("11121", 1u32), Some("KAU".to_string()));
+        assert_eq!(parser.rank("11121", "AAABA"), Some(BigUint::from(7u32)));
+
+        // Letters of longer tokens come later in the alphabet, so
+        // the default order is already sorted.
+        let parser = parser.by_output();
+        assert!(parser.is_by_output());
+        assert_eq!(parser.parse("2612"), vec!["BFAB", "BFL", "ZAB", "ZL"]);
+
+        let config = vec![("1".to_string(), 'B'), ("2".to_string(), 'C'), ("12".to_string(), 'A')];
+        let parser = Parser::new(&config);
+        assert_eq!(parser.parse("1212"), vec!["BCBC", "BCA", "ABC", "AA"]);
+        let parser = parser.by_output();
+        assert_eq!(parser.parse("1212"), vec!["AA", "ABC", "BCA", "BCBC"]);
+
+        // Equal outputs are tried in the order of their tokens.
+        let config = vec![("1".to_string(), vec!["A".to_string()]),
+                          ("11".to_string(), vec!["A".to_string()])];
+        let parser = Parser::from_strings(&config).by_output();
+        assert_eq!(parser.parse("11"), vec!["AA", "A"]);
+        let parser = parser.with_order(Order::LongestTokenFirst);
+        assert_eq!(parser.parse("11"), vec!["A", "AA"]);
+
+        // Outputs of a token keep their order, unless sorted.
+        let parser = Parser::from_multi(&vec![("1".to_string(), vec!['B', 'A'])])
+            .with_order(Order::LongestTokenFirst);
+        assert_eq!(parser.parse("1"), vec!["B", "A"]);
+        assert_eq!(parser.by_output().parse("1"), vec!["A", "B"]);
+        let parser = Parser::from_multi(&keypad_config()).with_order(Order::LongestTokenFirst);
+        assert_eq!(parser.parse("72"),
+                   vec!["PA", "PB", "PC", "QA", "QB", "QC", "RA", "RB", "RC", "SA", "SB", "SC"]);
+        let mut words = Parser::from_multi(&keypad_config())
+            .by_output()
+            .parse("2345");
+        let sorted = {
+            let mut sorted = words.clone();
+            sorted.sort();
+            sorted
+        };
+        assert_eq!(words, sorted);
+        words.dedup();
+        assert_eq!(words.len(), 81);
+    }
+
+    #[test]
+    fn count_matches_parse() {
+        let parser = Parser::new(&default_config());
+
+        for digits in &["", "1234", "1111111111", "2626", "100", "x"] {
+            assert_eq!(parser.count(digits),
+                       BigUint::from(parser.parse(digits).len()))
+        }
+
+        // Fibonacci: far too many to enumerate.
+        assert_eq!(parser.count(&"1".repeat(100)),
+                   BigUint::from(573147844013817084101u128))
+    }
+
+    #[test]
+    fn iter_is_lazy() {
+        let parser = Parser::new(&default_config());
+
+        for digits in &["", "1234", "2626", "100", "x", "1111111"] {
+            assert_eq!(parser.iter(digits).collect::<Vec<String>>(),
+                       parser.parse(digits))
+        }
+
+        let first = parser.iter(&"1".repeat(100)).take(3).collect::<Vec<_>>();
+        let mut a = "A".repeat(100);
+        assert_eq!(first[0], a);
+        a.truncate(98);
+        assert_eq!(first[1], a.clone() + "K");
+        a.truncate(97);
+        assert_eq!(first[2], a + "KA")
+    }
+
+    #[test]
+    fn very_long_input() {
+        let parser = Parser::new(&default_config());
+
+        // Only one way to read each "10".
+        let digits = "10".repeat(500_000);
+        assert_eq!(parser.parse(&digits), vec!["J".repeat(500_000)]);
+        assert!(parser.count(&digits).is_one())
+    }
+
+    #[test]
+    fn lattice_is_pruned() {
+        let parser = Parser::new(&default_config());
+        let edge = |start, end, token: &'static [char], output: &'static [char]|
+            Edge { start, end, token, output, weight: 0.0 };
+
+        let matches = parser.matches("120");
+        assert_eq!(matches.edges(),
+                   &[edge(0, 1, &['1'], &['A']), edge(0, 2, &['1', '2'], &['L']),
+                     edge(1, 2, &['2'], &['B']), edge(1, 3, &['2', '0'], &['T'])]);
+        assert_eq!(matches.reachable(), vec![true, true, true, true]);
+        assert_eq!(matches.live(), vec![true, true, false, true]);
+
+        let lattice = parser.lattice("120");
+        assert!(lattice.is_decodable());
+        assert_eq!(lattice.edges(), &[edge(0, 1, &['1'], &['A']), edge(1, 3, &['2', '0'], &['T'])]);
+        assert_eq!(lattice.positions(), vec![0, 1, 3]);
+        assert_eq!(lattice.edges_to(3).collect::<Vec<_>>(),
+                   vec![&edge(1, 3, &['2', '0'], &['T'])]);
+
+        assert!(!parser.lattice("100").is_decodable());
+        assert!(parser.lattice("100").edges().is_empty())
+    }
+
+    #[test]
+    fn parse_segmented() {
+        let parser = Parser::new(&default_config());
+        let piece = |start, end, token: &str, output: &str| Piece {
+            span: start .. end,
+            token: token.to_string(),
+            output: output.to_string()
+        };
+
+        let decodings = parser.parse_segmented("1234");
+        assert_eq!(decodings.iter().map(|d| &d.word[..]).collect::<Vec<_>>(),
+                   parser.parse("1234"));
+        assert_eq!(decodings[2],
+                   Decoding {
+                       word: "LCD".to_string(),
+                       pieces: vec![piece(0, 2, "12", "L"),
+                                    piece(2, 3, "3", "C"),
+                                    piece(3, 4, "4", "D")]
+                   });
+
+        assert!(parser.parse_segmented("100").is_empty())
+    }
+
+    #[test]
+    fn try_parse() {
+        let parser = Parser::new(&default_config());
+        let error = |position, character| Err(ParseError { position, character });
+
+        assert_eq!(parser.try_parse("1234"), Ok(parser.parse("1234")));
+        assert_eq!(parser.try_parse(""), Ok(vec![String::new()]));
+
+        assert_eq!(parser.try_parse("0"), error(0, '0'));
+        assert_eq!(parser.try_parse("12x4"), error(2, 'x'));
+
+        // "10" reads as "J", but nothing covers the second "0".
+        assert_eq!(parser.try_parse("1001"), error(2, '0'));
+
+        // "23" covers the "3" that the parse gets stuck at.
+        let parser = Parser::new(&vec![("12".to_string(), 'L'), ("23".to_string(), 'W')]);
+        assert_eq!(parser.try_parse("1234"), error(3, '4'));
+        assert_eq!(parser.try_parse("123"), error(2, '3'));
+    }
+
+    #[test]
+    fn try_new() {
+        assert!(Parser::try_new(&default_config()).is_ok());
+
+        let config = vec![("1".to_string(), 'A'),
+                          ("".to_string(), 'B'),
+                          ("1".to_string(), 'C'),
+                          ("2 ".to_string(), 'D'),
+                          ("2".to_string(), 'E'),
+                          ("2".to_string(), 'E')];
+        let diagnostics = Parser::try_new(&config).err().unwrap();
+        assert_eq!(diagnostics,
+                   vec![Diagnostic::ConflictingMapping {
+                            token: "1".to_string(),
+                            entries: vec![(0, 'A'), (2, 'C')]
+                        },
+                        Diagnostic::EmptyToken { index: 1 },
+                        Diagnostic::WhitespaceInToken {
+                            index: 3,
+                            token: "2 ".to_string()
+                        },
+                        Diagnostic::DuplicateToken {
+                            token: "2".to_string(),
+                            indices: vec![4, 5]
+                        }])
+    }
+
+    #[test]
+    fn multiple_outputs() {
+        let parser = Parser::from_multi(&keypad_config());
+
+        assert_eq!(parser.parse("23"),
+                   vec!["AD", "AE", "AF", "BD", "BE", "BF", "CD", "CE", "CF"]);
+        assert_eq!(parser.count("7777"), BigUint::from(4u32 * 4 * 4 * 4));
+        assert!(parser.count("1").is_zero());
+
+        let config = vec![("1".to_string(), vec!['A']),
+                          ("1".to_string(), vec!['B']),
+                          ("11".to_string(), vec!['K'])];
+        let parser = Parser::from_multi(&config);
+        assert_eq!(parser.parse("11"), vec!["AA", "AB", "BA", "BB", "K"])
+    }
+
+    #[test]
+    fn string_outputs() {
+        let strings = |outputs: &[&str]|
+            outputs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let config = vec![("27".to_string(), strings(&["TH"])),
+                          ("2".to_string(), strings(&["B"])),
+                          ("7".to_string(), strings(&["G", ""])),
+                          ("99".to_string(), strings(&["ING"]))];
+        let parser = Parser::from_strings(&config);
+
+        assert_eq!(parser.parse("2799"), vec!["BGING", "BING", "THING"]);
+        assert_eq!(parser.count("2799"), BigUint::from(3u32));
+        assert_eq!(parser.parse_segmented("2799")[2].pieces[0].output, "TH")
+    }
+
+    #[test]
+    fn generic_symbols() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        enum Op { Push, Pop, Swap }
+
+        let table = vec![(vec![1u8], vec![vec![Op::Push]]),
+                         (vec![2], vec![vec![Op::Pop]]),
+                         (vec![1, 2], vec![vec![Op::Swap, Op::Swap]])];
+        let parser = Parser::from_table(&table);
+
+        assert_eq!(parser.decode(&[1, 2, 2]),
+                   vec![vec![Op::Push, Op::Pop, Op::Pop],
+                        vec![Op::Swap, Op::Swap, Op::Pop]]);
+        assert_eq!(parser.count(&vec![1, 2, 1, 2]), BigUint::from(4u32));
+
+        // `Op` has no `Ord`, but token orders do not need it.
+        let parser = parser.with_order(Order::LongestTokenFirst);
+        assert_eq!(parser.decode(&[1, 2, 2]),
+                   vec![vec![Op::Swap, Op::Swap, Op::Pop],
+                        vec![Op::Push, Op::Pop, Op::Pop]]);
+        assert_eq!(parser.try_decode(&[1, 3][..]),
+                   Err(ParseError { position: 1, character: 3 }));
+
+        // Bytes to strings, through the same API as chars.
+        let parser = Parser::from_table(&vec![(b"ab".to_vec(), vec![vec!['X']])]);
+        assert_eq!(parser.parse(b"abab"), vec!["XX"])
+    }
+
+    #[test]
+    fn encode() {
+        let parser = Parser::new(&default_config());
+
+        assert_eq!(parser.encode("LCD"), Ok("1234".to_string()));
+        for word in parser.parse("1234") {
+            assert_eq!(parser.encode(&word), Ok("1234".to_string()));
+        }
+        assert_eq!(parser.encode("LcD"),
+                   Err(EncodeError { position: 1, character: 'c' }));
+
+        let config = vec![("1".to_string(), vec!['A']),
+                          ("01".to_string(), vec!['A', 'B'])];
+        let parser = Parser::from_multi(&config);
+        assert_eq!(parser.encode_all("AB"), vec!["101", "0101"]);
+        assert_eq!(parser.encode("AB"), Ok("101".to_string()));
+
+        let parser = Parser::new(&vec![("".to_string(), 'X'), ("1".to_string(), 'A')]);
+        assert_eq!(parser.parse("11"), vec!["AA"]);
+        assert!(parser.encode_all("AXA").is_empty());
+        assert_eq!(parser.encode_all("AA"), vec!["11"])
+    }
+
+    #[test]
+    fn decodability() {
+        let chars = |s: &str| s.chars().collect::<Vec<char>>();
+        let pieces = |pieces: &[(&str, &str)]|
+            pieces
             .iter()
-            .map(|s| &s[..])
-            .collect::<HashSet<&str>>();
+            .map(|&(token, output)| (chars(token), chars(output)))
+            .collect::<Vec<_>>();
+        let parser = |tokens: &[&str]|
+            Parser::from_strings(&tokens
+                                 .iter()
+                                 .map(|t| (t.to_string(), vec![t.to_string()]))
+                                 .collect());
+
+        assert_eq!(Parser::new(&default_config()).decodability(),
+                   Decodability::Ambiguous(Ambiguity {
+                       input: chars("11"),
+                       first: pieces(&[("1", "A"), ("1", "A")]),
+                       second: pieces(&[("11", "K")])
+                   }));
+
+        assert_eq!(Parser::from_multi(&keypad_config()).decodability(),
+                   Decodability::Ambiguous(Ambiguity {
+                       input: chars("2"),
+                       first: pieces(&[("2", "A")]),
+                       second: pieces(&[("2", "B")])
+                   }));
+
+        // Not prefix-free, but still uniquely decodable.
+        assert_eq!(parser(&["1", "10", "100"]).decodability(),
+                   Decodability::Unique);
+
+        // Also 011|1|01110 = 01110|1110, but that is longer.
+        assert_eq!(parser(&["1", "011", "01110", "1110"]).decodability(),
+                   Decodability::Ambiguous(Ambiguity {
+                       input: chars("111011"),
+                       first: pieces(&[("1", "1"), ("1", "1"), ("1", "1"), ("011", "011")]),
+                       second: pieces(&[("1110", "1110"), ("1", "1"), ("1", "1")])
+                   }))
+    }
+
+    #[test]
+    fn prefix_free() {
+        let chars = |s: &str| s.chars().collect::<Vec<char>>();
+
+        let parser = Parser::new(&default_config());
+        assert!(!parser.is_prefix_free());
+        let violations = parser.prefix_violations();
+        assert_eq!(violations.len(), 17);
+        assert_eq!(violations[0], (chars("1"), chars("10")));
+        assert_eq!(violations[16], (chars("2"), chars("26")));
+
+        let parser = Parser::from_multi(&keypad_config());
+        assert!(parser.is_prefix_free());
+        assert_eq!(parser.prefix_violations(), vec![]);
+        assert_eq!(parser.count("2345"), BigUint::from(81u32));
+        assert_eq!(parser.try_parse("2315"),
+                   Err(ParseError { position: 2, character: '1' }));
+
+        let config = vec![("0".to_string(), 'A'),
+                          ("10".to_string(), 'B'),
+                          ("11".to_string(), 'C')];
+        let parser = Parser::new(&config);
+        assert!(parser.is_prefix_free());
+        assert_eq!(parser.parse("01011"), vec!["ABC"]);
+        assert_eq!(parser.parse("0101"), Vec::<String>::new());
+        assert_eq!(parser.lattice("1100").edges().len(), 3)
+    }
+
+    #[test]
+    fn most_and_least_ambiguous() {
+        let parser = Parser::new(&default_config());
+        let results = |found: Vec<(Vec<char>, BigUint)>|
+            found
+            .into_iter()
+            .map(|(input, count)| (input.into_iter().collect::<String>(), count))
+            .collect::<Vec<_>>();
+        let big = |n: u32| BigUint::from(n);
+
+        // Any digit can end the input after "1".
+        assert_eq!(results(parser.most_ambiguous(6, 3)),
+                   vec![("111111".to_string(), big(13)),
+                        ("111112".to_string(), big(13)),
+                        ("111113".to_string(), big(13))]);
+        assert_eq!(parser.most_ambiguous(40, 1)[0].1, parser.count(&"1".repeat(40)));
+
+        assert_eq!(results(parser.least_ambiguous(3, 2)),
+                   vec![("000".to_string(), big(0)),
+                        ("001".to_string(), big(0))]);
+
+        // Every key has at least three letters.
+        let parser = Parser::from_multi(&keypad_config());
+        assert_eq!(results(parser.least_ambiguous(4, 2)),
+                   vec![("2222".to_string(), big(81)),
+                        ("2223".to_string(), big(81))]);
+        assert_eq!(results(parser.most_ambiguous(3, 1)),
+                   vec![("777".to_string(), big(64))]);
+
+        assert_eq!(parser.most_ambiguous(0, 5), vec![(vec![], big(1))])
+    }
+
+    #[test]
+    fn wildcards() {
+        let parser = Parser::new(&default_config());
+
+        let matches = parser.parse_pattern("12?4", '?');
+        assert_eq!(matches[0],
+                   PatternMatch { filling: "1214".to_string(), word: "ABAD".to_string() });
+        for m in &matches {
+            assert!(parser.parse(&m.filling).contains(&m.word));
+        }
+
+        let total = (0 .. 10)
+            .map(|d| parser.count(&format!("12{}4", d)))
+            .sum::<BigUint>();
+        assert_eq!(BigUint::from(matches.len()), total);
+        assert_eq!(parser.count_pattern("12?4", '?'), total);
+
+        assert_eq!(parser.parse_pattern("?0", '?'),
+                   vec![PatternMatch { filling: "10".to_string(), word: "J".to_string() },
+                        PatternMatch { filling: "20".to_string(), word: "T".to_string() }]);
+    }
+
+    #[test]
+    fn constrained() {
+        let parser = Parser::new(&default_config());
+
+        assert_eq!(parser.parse_constrained("1234", &OutputPattern::wildcard("L?D", '?')),
+                   vec!["LCD"]);
+        assert_eq!(parser.parse_constrained("1234", &OutputPattern::wildcard("??D", '?')),
+                   vec!["AWD", "LCD"]);
+
+        let mut pattern = OutputPattern::wildcard("A", '?');
+        pattern.lengths = 2 ..= 4;
+        assert_eq!(parser.parse_constrained("1234", &pattern), vec!["ABCD", "AWD"]);
+
+        let mut pattern = OutputPattern::wildcard("?K?", '?');
+        pattern.lengths = 3 ..= 6;
+        let digits = "1111111111";
+        let expected = parser
+            .parse(digits)
+            .into_iter()
+            .filter(|word| word.len() >= 3 && word.len() <= 6 && &word[1..2] == "K")
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(parser.parse_constrained(digits, &pattern), expected);
+        assert_eq!(parser.count_constrained(digits, &pattern),
+                   BigUint::from(expected.len()));
+
+        let mut pattern = OutputPattern::wildcard("?K", '?');
+        pattern.lengths = 4 ..= usize::MAX;
+        let expected = parser
+            .parse(digits)
+            .into_iter()
+            .filter(|word| word.len() >= 4 && &word[1..2] == "K")
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(parser.parse_constrained(digits, &pattern), expected);
+
+        // Only as many lengths are told apart as the pattern needs.
+        let mut pattern = OutputPattern::wildcard("K", '?');
+        pattern.lengths = 0 ..= usize::MAX;
+        assert_eq!(parser.count_constrained(&"1".repeat(20000), &pattern),
+                   parser.count(&"1".repeat(19998)));
+
+        assert!(parser.parse_constrained("1234", &OutputPattern::wildcard("?", '?')).is_empty());
+        assert_eq!(parser.count_constrained("", &OutputPattern::new(vec![])),
+                   BigUint::from(1u32));
+    }
+
+    #[test]
+    fn dictionary() {
+        let parser = Parser::new(&default_config());
+        let dictionary = Dictionary::from_words(vec!["A", "AB", "BA", "ABBA", "LU"]);
+
+        assert_eq!(parser.parse_words("1221", &dictionary), vec!["ABBA", "LU"]);
+        assert_eq!(parser.parse_word_sequences("1221", &dictionary),
+                   vec![vec!["ABBA"], vec!["AB", "BA"], vec!["LU"]]);
+        assert!(parser.parse_words("12212", &dictionary).is_empty());
+        assert_eq!(parser.parse_word_sequences("12211221", &dictionary).len(), 9);
+
+        // All the splits of ABAB, then of ABL, LAB and LL.
+        let dictionary = Dictionary::from_words(vec!["A", "AB", "BA", "B", "L", "LA", "ABA"]);
+        assert_eq!(parser.parse("1212"), vec!["ABAB", "ABL", "AUB", "LAB", "LL"]);
+        assert_eq!(parser.parse_word_sequences("1212", &dictionary),
+                   vec![vec!["ABA", "B"],
+                        vec!["AB", "AB"],
+                        vec!["AB", "A", "B"],
+                        vec!["A", "BA", "B"],
+                        vec!["A", "B", "AB"],
+                        vec!["A", "B", "A", "B"],
+                        vec!["AB", "L"],
+                        vec!["A", "B", "L"],
+                        vec!["LA", "B"],
+                        vec!["L", "AB"],
+                        vec!["L", "A", "B"],
+                        vec!["L", "L"]]);
+
+        let path = ::std::env::temp_dir()
+            .join(format!("number_words_dictionary_{}.txt", ::std::process::id()));
+        ::std::fs::write(&path, "  ABBA\n\nLU \n").unwrap();
+        let dictionary = Dictionary::from_file(&path);
+        ::std::fs::remove_file(&path).unwrap();
+        let dictionary = dictionary.unwrap();
+        assert!(dictionary.contains(&['L', 'U']));
+        assert!(!dictionary.contains(&['A']));
+        assert_eq!(parser.parse_words("1221", &dictionary), vec!["ABBA", "LU"]);
+        assert!(Dictionary::from_file(&path).is_err());
+    }
+
+    #[test]
+    fn best_k() {
+        let parser = Parser::from_multi(&keypad_config());
+        let model = NgramModel::train(3, "THE CAT SAT ON THE MAT AND THE BAT ATE THE RAT");
+
+        for digits in &["843", "228", "2282", ""] {
+            let mut expected = parser
+                .parse(digits)
+                .into_iter()
+                .map(|word| {
+                    let score = model.log_prob(&word);
+                    (word, score)
+                })
+                .collect::<Vec<_>>();
+            expected.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+            let best = parser.best_k(digits, 5, &model);
+            assert_eq!(best.len(), expected.len().min(5));
+            for ((word, score), (_, expected_score)) in best.iter().zip(&expected) {
+                assert!((score - expected_score).abs() < 1e-9);
+                assert!((score - model.log_prob(word)).abs() < 1e-9);
+            }
+        }
+
+        assert_eq!(parser.best_k("843", 1, &model)[0].0, "THE");
+        assert!(parser.best_k("1", 3, &model).is_empty());
+    }
+
+    #[test]
+    fn weights() {
+        let config = vec![("1".to_string(), 'A', -1.0),
+                          ("2".to_string(), 'B', -1.0),
+                          ("12".to_string(), 'L', -3.0),
+                          ("12".to_string(), 'M', -1.5)];
+        let parser = Parser::from_weighted(&config);
+
+        assert_eq!(parser.parse("12"), vec!["AB", "L", "M"]);
+        assert_eq!(parser.parse_best("12"), Some(("M".to_string(), -1.5)));
+        assert_eq!(parser.parse_best("1212"), Some(("MM".to_string(), -3.0)));
+        assert_eq!(parser.parse_best("3"), None);
+
+        let total = (-2.0f64).exp() + (-3.0f64).exp() + (-1.5f64).exp();
+        assert!((parser.log_total("12") - total.ln()).abs() < 1e-12);
+        assert!((parser.log_total("1212") - 2.0 * total.ln()).abs() < 1e-12);
+        assert_eq!(parser.log_total("3"), f64::NEG_INFINITY);
+
+        // Weights compare bit for bit, keeping lattices `Eq`.
+        fn equivalence<T: Eq>(x: T, y: T) -> bool {
+            x == y
+        }
+        assert!(equivalence(parser.lattice("1212"), parser.lattice("1212")));
+        assert_eq!(parser.lattice("12").edges()[2].weight, -1.5);
+
+        // Parses of probability 0 are still parses.
+        let config = vec![("1".to_string(), 'A', f64::NEG_INFINITY),
+                          ("11".to_string(), 'K', f64::NEG_INFINITY)];
+        let parser = Parser::from_weighted(&config);
+        assert_eq!(parser.parse_best("1"), Some(("A".to_string(), f64::NEG_INFINITY)));
+        assert_eq!(parser.parse_best("11"), Some(("AA".to_string(), f64::NEG_INFINITY)));
+        assert_eq!(parser.log_total("11"), f64::NEG_INFINITY);
+
+        // Unweighted, every parse is as likely.
+        let parser = Parser::new(&default_config());
+        assert_eq!(parser.parse_best("1234"), Some(("ABCD".to_string(), 0.0)));
+        assert!((parser.log_total("1234") - 3f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sample() {
+        let parser = Parser::new(&default_config());
+        let mut rng = StdRng::seed_from_u64(2014);
+
+        // "1111" has 5 parses.
+        let mut seen = HashMap::new();
+        for _ in 0 .. 5000 {
+            *seen.entry(parser.sample("1111", &mut rng).unwrap()).or_insert(0) += 1;
+        }
+        assert_eq!(seen.len(), 5);
+        assert!(seen.values().all(|&n| n > 850 && n < 1150));
+
+        assert_eq!(parser.sample("", &mut rng), Some("".to_string()));
+        assert_eq!(parser.sample("30", &mut rng), None);
+
+        let long = "1".repeat(300);
+        let word = parser.sample(&long, &mut rng).unwrap();
+        assert!(parser.encode_all(&word).contains(&long));
+
+        let draws = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0 .. 10).map(|_| parser.sample("11111111", &mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(7), draws(7));
+
+        // L is three times as likely as AB.
+        let config = vec![("1".to_string(), 'A', 0.0),
+                          ("2".to_string(), 'B', 0.0),
+                          ("12".to_string(), 'L', 3f64.ln())];
+        let parser = Parser::from_weighted(&config);
+        let ls = (0 .. 4000)
+            .filter(|_| parser.sample_weighted("12", &mut rng).unwrap() == "L")
+            .count();
+        assert!(ls > 2850 && ls < 3150);
+        assert_eq!(parser.sample_weighted("3", &mut rng), None);
+
+        // Without any probability, every parse is as likely.
+        let config = vec![("1".to_string(), 'A', f64::NEG_INFINITY),
+                          ("11".to_string(), 'K', f64::NEG_INFINITY)];
+        let parser = Parser::from_weighted(&config);
+        assert_eq!(parser.sample_weighted("1", &mut rng), Some("A".to_string()));
+        let ks = (0 .. 4000)
+            .filter(|_| parser.sample_weighted("11", &mut rng).unwrap() == "K")
+            .count();
+        assert!(ks > 1850 && ks < 2150);
+    }
+
+    #[test]
+    fn nth_and_rank() {
+        let parser = Parser::new(&default_config());
+
+        let digits = "1121231234";
+        let words = parser.parse(digits);
+        for (k, word) in words.iter().enumerate() {
+            assert_eq!(parser.nth(digits, k).as_ref(), Some(word));
+            assert_eq!(parser.rank(digits, word), Some(BigUint::from(k)));
+        }
+        assert_eq!(parser.nth(digits, words.len()), None);
+        assert_eq!(parser.rank(digits, "ZZ"), None);
+        assert_eq!(parser.rank("1234", "LCD"), Some(BigUint::from(2u32)));
+
+        let long = "12".repeat(100);
+        let k = BigUint::from(10u32).pow(20);
+        let word = parser.nth(&long, k.clone()).unwrap();
+        assert_eq!(parser.rank(&long, &word), Some(k));
+        assert_eq!(parser.nth(&long, parser.count(&long)), None);
+
+        // The first of several ways to parse the same word.
+        let parser = Parser::from_strings(&vec![("1".to_string(), vec!["A".to_string()]),
+                                                ("11".to_string(), vec!["AA".to_string()])]);
+        assert_eq!(parser.parse("11"), vec!["AA", "AA"]);
+        assert_eq!(parser.rank("11", "AA"), Some(BigUint::zero()));
+    }
+
+    #[test]
+    fn count_long_input() {
+        let parser = Parser::new(&default_config());
+        let digits = "1".repeat(200);
+
+        assert_eq!(parser.checked_count(&digits), None);
+
+        // Fibonacci number F(201).
+        let expected = "453973694165307953197296969697410619233826"
+            .parse::<BigUint>()
+            .unwrap();
+        assert_eq!(parser.count(&digits), expected);
 
-        assert_eq!(actual_set, expected_set)
+        let m = 1_000_000_007;
+        assert_eq!(BigUint::from(parser.count_mod(&digits, m)),
+                   expected % m)
     }
 }
@@ -0,0 +1,191 @@
+//! Character n-gram language model, to rank words by how plausible
+//! they look.
+//!
+//! Probabilities are interpolated with Witten–Bell smoothing: each
+//! context keeps some mass for the lower-order estimate, in proportion
+//! to how many different chars have followed it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::mem;
+use std::path::Path;
+
+use super::{Lattice, Parser, Symbols};
+
+/// Pads the start of a word, and marks its end.
+const BOUNDARY: char = '\0';
+
+pub struct NgramModel {
+    /// Each char is predicted from the `order - 1` chars before it.
+    order: usize,
+
+    /// For each context of fewer than `order` chars, the chars seen
+    /// after it.
+    contexts: HashMap<Vec<char>, Followers>,
+
+    /// Number of different chars seen, including the end of words.
+    vocabulary: usize
+}
+
+#[derive(Default)]
+struct Followers {
+    total: u64,
+    counts: HashMap<char, u64>
+}
+
+impl NgramModel {
+    /// Model of order `order` trained on each whitespace-separated
+    /// word of `text`. Chars are taken as they are, so text meant to
+    /// rank the words of the configs here should be upper case.
+    ///
+    /// Panics if `order` is zero.
+    pub fn train(order: usize, text: &str) -> NgramModel {
+        assert!(order > 0, "n-gram order must be positive");
+
+        let mut contexts: HashMap<Vec<char>, Followers> = HashMap::new();
+        for word in text.split_whitespace() {
+            let padded = padded(order, word.chars())
+                .chain(Some(BOUNDARY))
+                .collect::<Vec<char>>();
+            for i in order - 1 .. padded.len() {
+                for context_len in 0 .. order {
+                    let followers = contexts
+                        .entry(padded[i - context_len .. i].to_vec())
+                        .or_default();
+                    followers.total += 1;
+                    *followers.counts.entry(padded[i]).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let vocabulary = contexts.get(&[][..]).map_or(0, |unigrams| unigrams.counts.len());
+        NgramModel { order, contexts, vocabulary }
+    }
+
+    /// `train` on the contents of a file.
+    pub fn from_file<P: AsRef<Path>>(order: usize, path: P) -> io::Result<NgramModel> {
+        Ok(NgramModel::train(order, &fs::read_to_string(path)?))
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Natural logarithm of the probability of `word`, including its
+    /// end.
+    pub fn log_prob(&self, word: &str) -> f64 {
+        let mut context = padded(self.order, None).collect::<Vec<char>>();
+        let mut score = 0.0;
+        for c in word.chars().chain(Some(BOUNDARY)) {
+            score += self.next_log_prob(&mut context, c);
+        }
+        score
+    }
+
+    /// Log-probability of `c` after `context`, which then moves on past
+    /// `c`.
+    fn next_log_prob(&self, context: &mut Vec<char>, c: char) -> f64 {
+        let log_prob = self.prob(context, c).ln();
+        if !context.is_empty() {
+            context.remove(0);
+            context.push(c);
+        }
+        log_prob
+    }
+
+    /// Probability of `c` after the `order - 1` chars of `context`.
+    /// Chars never seen share the mass of one more char.
+    fn prob(&self, context: &[char], c: char) -> f64 {
+        let mut prob = 1.0 / (self.vocabulary + 1) as f64;
+        for context_len in 0 .. self.order {
+            if let Some(followers) = self.contexts.get(&context[context.len() - context_len ..]) {
+                let seen = *followers.counts.get(&c).unwrap_or(&0) as f64;
+                let types = followers.counts.len() as f64;
+                prob = (seen + types * prob) / (followers.total as f64 + types);
+            }
+        }
+        prob
+    }
+
+    /// The `k` paths of `lattice` whose outputs have the highest
+    /// log-probability. The future of a path only depends on its
+    /// position and its last `order - 1` chars, so keeping the `k`
+    /// best paths for each of those is enough.
+    fn best_k<I>(&self, lattice: &Lattice<'_, I, char>, k: usize) -> Vec<(String, f64)> {
+        // Best paths so far, as a tree of edges.
+        let mut paths: Vec<(Option<usize>, &[char])> = vec![(None, &[])];
+
+        // For each position and context, paths reaching it, with their
+        // score.
+        let mut beams = vec![BTreeMap::new(); lattice.len() + 1];
+        beams[0].insert(padded(self.order, None).collect::<Vec<char>>(), vec![(0.0, 0)]);
+
+        for position in 0 ..= lattice.len() {
+            let beam = mem::take(&mut beams[position]);
+            for (context, mut scored) in beam {
+                sort_best(&mut scored);
+                scored.truncate(k);
+                if position == lattice.len() {
+                    beams[position].insert(context, scored);
+                    continue;
+                }
+
+                for edge in lattice.edges_from(position) {
+                    let mut next = context.clone();
+                    let step = edge.output
+                        .iter()
+                        .map(|&c| self.next_log_prob(&mut next, c))
+                        .sum::<f64>();
+                    let reached = beams[edge.end].entry(next).or_insert_with(Vec::new);
+                    for &(score, path) in &scored {
+                        reached.push((score + step, paths.len()));
+                        paths.push((Some(path), edge.output));
+                    }
+                }
+            }
+        }
+
+        let mut best = Vec::new();
+        for (mut context, scored) in mem::take(&mut beams[lattice.len()]) {
+            let end = self.next_log_prob(&mut context, BOUNDARY);
+            best.extend(scored.into_iter().map(|(score, path)| (score + end, path)));
+        }
+        sort_best(&mut best);
+        best.truncate(k);
+
+        best.into_iter()
+            .map(|(score, mut path)| {
+                let mut pieces = Vec::new();
+                while let (Some(parent), output) = paths[path] {
+                    pieces.push(output);
+                    path = parent;
+                }
+                (pieces.iter().rev().flat_map(|output| output.iter()).collect(), score)
+            })
+            .collect()
+    }
+}
+
+/// `chars` after the context of the start of a word.
+fn padded(order: usize, chars: impl IntoIterator<Item = char>) -> impl Iterator<Item = char> {
+    vec![BOUNDARY; order - 1].into_iter().chain(chars)
+}
+
+/// Highest score first.
+fn sort_best(scored: &mut [(f64, usize)]) {
+    scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+}
+
+impl<I: Eq + Hash + Clone> Parser<I, char> {
+    /// The `k` words of `digits` most likely according to `model`,
+    /// with their log-probabilities, best first. Ties are broken
+    /// arbitrarily.
+    pub fn best_k<S: Symbols<I> + ?Sized>(&self,
+                                          digits: &S,
+                                          k: usize,
+                                          model: &NgramModel) -> Vec<(String, f64)> {
+        model.best_k(&self.lattice(digits), k)
+    }
+}
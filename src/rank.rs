@@ -0,0 +1,125 @@
+//! Index of a parse in the order of `parse`, and back.
+//!
+//! Parses are paths through the lattice, taken edge by edge in the
+//! order of `Lattice::edges_from`. The paths before a given one are
+//! those leaving it at some position by an earlier edge, and there are
+//! as many of those as there are paths from the ends of these edges.
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use super::{Edge, Lattice, Parser, Symbols};
+
+impl<'a, I, O> Lattice<'a, I, O> {
+    /// Path number `k` from the start to the end, counting from zero,
+    /// or `None` if there are not that many paths.
+    pub fn nth(&self, k: &BigUint) -> Option<Vec<Edge<'a, I, O>>> {
+        let counts = self.counts();
+        if *k >= counts[0] {
+            return None;
+        }
+
+        let mut k = k.clone();
+        let mut path = Vec::new();
+        let mut position = 0;
+        while position < self.len() {
+            for edge in self.edges_from(position) {
+                if k < counts[edge.end] {
+                    path.push(*edge);
+                    position = edge.end;
+                    break;
+                }
+                k -= &counts[edge.end];
+            }
+        }
+        Some(path)
+    }
+
+    /// Number of the first path from the start to the end producing
+    /// `output`, or `None` if there is none.
+    /// Pairs of a position and a length of `output` from which it
+    /// cannot be finished are remembered, so each is explored once.
+    pub fn rank(&self, output: &[O]) -> Option<BigUint>
+        where O: PartialEq
+    {
+        // Position, length of output so far, and index of the next
+        // edge to try.
+        let mut stack = vec![(0, 0, 0)];
+        let mut dead = HashSet::new();
+        while let Some(&(position, len, next)) = stack.last() {
+            if position == self.len() && len == output.len() {
+                break;
+            }
+
+            let edges = self.edges_from(position);
+            let found = (next .. edges.len()).find(|&i| {
+                let edge = &edges[i];
+                output[len..].starts_with(edge.output) &&
+                    !dead.contains(&(edge.end, len + edge.output.len()))
+            });
+            match found {
+                Some(i) => {
+                    stack.last_mut().unwrap().2 = i + 1;
+                    stack.push((edges[i].end, len + edges[i].output.len(), 0));
+                }
+                None => {
+                    dead.insert((position, len));
+                    stack.pop();
+                }
+            }
+        }
+        if stack.is_empty() {
+            return None;
+        }
+
+        // Each entry but the last left by the edge before its next one.
+        let counts = self.counts();
+        let rank = stack[.. stack.len() - 1]
+            .iter()
+            .flat_map(|&(position, _, next)| &self.edges_from(position)[.. next - 1])
+            .fold(BigUint::zero(), |total, edge| total + &counts[edge.end]);
+        Some(rank)
+    }
+
+    fn counts(&self) -> Vec<BigUint> {
+        self.suffix_counts(BigUint::zero(), BigUint::one(), |total, n| total + n)
+    }
+}
+
+impl<I: Eq + Hash + Clone, O: Clone> Parser<I, O> {
+    /// Output sequence number `k` of `decode`, counting from zero,
+    /// without producing the ones before it.
+    pub fn nth_decoding<S: Symbols<I> + ?Sized>(&self, input: &S, k: impl Into<BigUint>)
+                                                -> Option<Vec<O>> {
+        self.lattice(input)
+            .nth(&k.into())
+            .map(|path| path.iter().flat_map(|edge| edge.output.iter().cloned()).collect())
+    }
+
+    /// Index of the first occurrence of `output` in `decode`.
+    pub fn rank_decoding<S: Symbols<I> + ?Sized>(&self, input: &S, output: &[O])
+                                                 -> Option<BigUint>
+        where O: PartialEq
+    {
+        self.lattice(input).rank(output)
+    }
+}
+
+impl<I: Eq + Hash + Clone> Parser<I, char> {
+    /// Word number `k` of `parse`, counting from zero, or `None` if
+    /// there are not that many words. Takes time linear in the length
+    /// of `digits`, however large `k` is.
+    pub fn nth<S: Symbols<I> + ?Sized>(&self, digits: &S, k: impl Into<BigUint>)
+                                       -> Option<String> {
+        self.nth_decoding(digits, k).map(|word| word.into_iter().collect())
+    }
+
+    /// Index of `word` in `parse`, or `None` if it is not a parse of
+    /// `digits`. If `word` can be parsed in several ways, the index of
+    /// the first.
+    pub fn rank<S: Symbols<I> + ?Sized>(&self, digits: &S, word: &str) -> Option<BigUint> {
+        self.rank_decoding(digits, &word.chars().collect::<Vec<char>>())
+    }
+}
@@ -0,0 +1,122 @@
+//! Random parses, drawn without enumerating them.
+//!
+//! A path is built from the start, choosing each edge with probability
+//! proportional to the parses going through it: the number of paths
+//! from its end for uniform sampling, or their total probability
+//! times its own for weighted sampling.
+
+use num_bigint::{BigUint, RandBigInt};
+use num_traits::{One, Zero};
+use rand::Rng;
+use std::hash::Hash;
+
+use super::{Edge, Lattice, Parser, Symbols};
+
+impl<'a, I, O> Lattice<'a, I, O> {
+    /// Path from the start to the end, each one equally likely, or
+    /// `None` if there is no path.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Vec<Edge<'a, I, O>>> {
+        let counts = self.suffix_counts(BigUint::zero(), BigUint::one(), |total, n| total + n);
+        self.sample_with(|position, edges| {
+            let mut index = rng.gen_biguint_below(&counts[position]);
+            edges.iter().position(|edge| {
+                let through = &counts[edge.end];
+                if index < *through {
+                    true
+                } else {
+                    index -= through;
+                    false
+                }
+            })
+        })
+    }
+
+    /// Path from the start to the end, drawn with probability
+    /// proportional to the exponential of its total weight, or `None`
+    /// if there is no path. If every path has weight
+    /// `f64::NEG_INFINITY`, each is equally likely.
+    pub fn sample_weighted<R: Rng + ?Sized>(&self, rng: &mut R)
+                                            -> Option<Vec<Edge<'a, I, O>>> {
+        let totals = self.suffix_log_totals();
+        if totals[0] == f64::NEG_INFINITY {
+            return self.sample(rng);
+        }
+        self.sample_with(|position, edges| {
+            let mut u = rng.gen::<f64>();
+            let chosen = edges.iter().position(|edge| {
+                let p = (edge.weight + totals[edge.end] - totals[position]).exp();
+                if u < p {
+                    true
+                } else {
+                    u -= p;
+                    false
+                }
+            });
+            // Rounding may leave `u` just past the last edge with any
+            // probability.
+            chosen.or_else(|| edges.iter().rposition(|edge| totals[edge.end] > f64::NEG_INFINITY))
+        })
+    }
+
+    /// Path from the start, where `choose(position, edges)` picks the
+    /// index of the next edge among those from `position`.
+    fn sample_with(&self, mut choose: impl FnMut(usize, &[Edge<'a, I, O>]) -> Option<usize>)
+                   -> Option<Vec<Edge<'a, I, O>>> {
+        if !self.is_decodable() {
+            return None;
+        }
+        let mut path = Vec::new();
+        let mut position = 0;
+        while position < self.len() {
+            let edges = self.edges_from(position);
+            let edge = edges[choose(position, edges)?];
+            path.push(edge);
+            position = edge.end;
+        }
+        Some(path)
+    }
+}
+
+impl<I: Eq + Hash + Clone, O: Clone> Parser<I, O> {
+    /// Output sequence drawn uniformly among all those of `decode`,
+    /// or `None` if there are none.
+    pub fn decode_sample<S, R>(&self, input: &S, rng: &mut R) -> Option<Vec<O>>
+        where S: Symbols<I> + ?Sized,
+              R: Rng + ?Sized
+    {
+        self.lattice(input).sample(rng).map(|path| outputs(&path))
+    }
+
+    /// Output sequence drawn with the probability given by the weights
+    /// of its tokens, or `None` if there are none.
+    pub fn decode_sample_weighted<S, R>(&self, input: &S, rng: &mut R) -> Option<Vec<O>>
+        where S: Symbols<I> + ?Sized,
+              R: Rng + ?Sized
+    {
+        self.lattice(input).sample_weighted(rng).map(|path| outputs(&path))
+    }
+}
+
+impl<I: Eq + Hash + Clone> Parser<I, char> {
+    /// Word drawn uniformly among all those of `parse`, or `None` if
+    /// there are none. Seed `rng` for repeatable results.
+    pub fn sample<S, R>(&self, digits: &S, rng: &mut R) -> Option<String>
+        where S: Symbols<I> + ?Sized,
+              R: Rng + ?Sized
+    {
+        self.decode_sample(digits, rng).map(|word| word.into_iter().collect())
+    }
+
+    /// Word drawn with the probability given by the weights of its
+    /// tokens, or `None` if there are none.
+    pub fn sample_weighted<S, R>(&self, digits: &S, rng: &mut R) -> Option<String>
+        where S: Symbols<I> + ?Sized,
+              R: Rng + ?Sized
+    {
+        self.decode_sample_weighted(digits, rng).map(|word| word.into_iter().collect())
+    }
+}
+
+fn outputs<I, O: Clone>(path: &[Edge<'_, I, O>]) -> Vec<O> {
+    path.iter().flat_map(|edge| edge.output.iter().cloned()).collect()
+}
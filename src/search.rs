@@ -0,0 +1,223 @@
+//! Inputs of a given length with the most or the fewest parses.
+//!
+//! Best-first branch and bound over prefixes of the input. The number
+//! of parses of each prefix is updated one symbol at a time, as in the
+//! lattice, and bounds the number of parses of its completions.
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use std::cmp::{self, Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+
+use super::Parser;
+use super::trie::Trie;
+
+/// Prefix of an input, ordered for the search queue.
+struct Node<I, K> {
+    /// Bound on the number of parses of any completion.
+    key: K,
+    input: Vec<I>,
+
+    /// Number of parses of the last few prefixes of `input`, up to the
+    /// length of the longest token, ending with `input` itself.
+    window: Vec<BigUint>
+}
+
+// Best key first, then smallest input, so that equally good inputs
+// come out in lexicographic order.
+impl<I: Ord, K: Ord> Ord for Node<I, K> {
+    fn cmp(&self, other: &Node<I, K>) -> Ordering {
+        self.key
+            .cmp(&other.key)
+            .then_with(|| other.input.cmp(&self.input))
+    }
+}
+
+impl<I: Ord, K: Ord> PartialOrd for Node<I, K> {
+    fn partial_cmp(&self, other: &Node<I, K>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I: Ord, K: Ord> PartialEq for Node<I, K> {
+    fn eq(&self, other: &Node<I, K>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<I: Ord, K: Ord> Eq for Node<I, K> {}
+
+/// What the search needs to know about the table.
+struct Tokens<I> {
+    /// Symbols appearing in tokens, sorted.
+    alphabet: Vec<I>,
+
+    /// Reversed tokens, with their number of outputs.
+    reversed: Trie<I, usize>,
+
+    /// For each length, the most outputs of any token of that length.
+    most_outputs: Vec<usize>
+}
+
+impl<I: Ord + Eq + Hash + Clone> Tokens<I> {
+    fn new<O>(parser: &Parser<I, O>) -> Tokens<I> {
+        let mut alphabet = Vec::new();
+        let mut reversed = Trie::new();
+        let mut most_outputs = vec![0];
+        for (token, entry) in parser.trie.entries() {
+            let outputs = &entry.outputs;
+            if token.is_empty() || outputs.is_empty() {
+                continue;
+            }
+            alphabet.extend(token.iter().cloned());
+            if most_outputs.len() <= token.len() {
+                most_outputs.resize(token.len() + 1, 0);
+            }
+            most_outputs[token.len()] = cmp::max(most_outputs[token.len()],
+                                                 outputs.len());
+            reversed.insert(token.into_iter().rev(), outputs.len());
+        }
+        alphabet.sort();
+        alphabet.dedup();
+
+        Tokens { alphabet, reversed, most_outputs }
+    }
+
+    fn max_len(&self) -> usize {
+        self.most_outputs.len() - 1
+    }
+
+    /// Window after appending `symbol` to `input`.
+    fn extend(&self, input: &[I], window: &[BigUint], symbol: &I) -> Vec<BigUint> {
+        let backwards = ::std::iter::once(symbol)
+            .chain(input.iter().rev())
+            .take(self.max_len())
+            .cloned()
+            .collect::<Vec<I>>();
+        let count = self.reversed
+            .prefixes(&backwards)
+            .fold(BigUint::zero(), |total, (len, &outputs)|
+                  total + &window[window.len() - len] * outputs);
+
+        let mut window = window.to_vec();
+        window.push(count);
+        if window.len() > self.max_len() {
+            window.remove(0);
+        }
+        window
+    }
+
+    /// Most parses of any input of each length up to `n`, ignoring
+    /// which tokens can actually follow each other.
+    fn most_parses(&self, n: usize) -> Vec<BigUint> {
+        let mut most = vec![BigUint::one()];
+        for r in 1 ..= n {
+            let total = (1 ..= cmp::min(self.max_len(), r))
+                .fold(BigUint::zero(), |total, len|
+                      total + &most[r - len] * self.most_outputs[len]);
+            most.push(total);
+        }
+        most
+    }
+}
+
+impl<I: Ord + Eq + Hash + Clone, O> Parser<I, O> {
+    /// The `k` inputs of length `n` with the most parses, with their
+    /// number of parses, most first and then in lexicographic order.
+    /// Inputs are made of the symbols appearing in tokens.
+    pub fn most_ambiguous(&self, n: usize, k: usize) -> Vec<(Vec<I>, BigUint)> {
+        let tokens = Tokens::new(self);
+        let most = tokens.most_parses(n);
+
+        // A parse of a completion goes through some last position
+        // in the window, from which it jumps past the end of the
+        // prefix and then does at best like any input.
+        let upper_bound = |i: usize, window: &[BigUint]| {
+            window
+                .iter()
+                .rev()
+                .enumerate()
+                .fold(BigUint::zero(), |total, (back, count)| {
+                    let rest = n - i + back;
+                    let jumps = if back == 0 {
+                        most[rest].clone()
+                    } else {
+                        (back + 1 ..= cmp::min(tokens.max_len(), rest))
+                            .fold(BigUint::zero(), |total, len|
+                                  total + &most[rest - len] * tokens.most_outputs[len])
+                    };
+                    total + count * jumps
+                })
+        };
+
+        search(&tokens, n, k, upper_bound)
+    }
+
+    /// The `k` inputs of length `n` with the fewest parses, with their
+    /// number of parses, fewest first and then in lexicographic order.
+    /// Inputs are made of the symbols appearing in tokens, so these are
+    /// inputs with no parse at all whenever there are any.
+    pub fn least_ambiguous(&self, n: usize, k: usize) -> Vec<(Vec<I>, BigUint)> {
+        let tokens = Tokens::new(self);
+
+        // Fewest parses of any input of each length, found by
+        // searching each length in turn. A completion has at least
+        // as many parses as those going through the end of the prefix.
+        let mut fewest: Vec<BigUint> = vec![BigUint::one()];
+        for r in 1 ..= n {
+            let least = {
+                let lower_bound = |i: usize, window: &[BigUint]| {
+                    let rest = fewest.get(r - i).cloned().unwrap_or_else(BigUint::zero);
+                    Reverse(window.last().unwrap() * rest)
+                };
+                if r == n {
+                    return search(&tokens, n, k, lower_bound);
+                }
+                search(&tokens, r, 1, lower_bound)
+            };
+            fewest.push(least
+                        .into_iter()
+                        .next()
+                        .map_or_else(BigUint::zero, |(_, count)| count));
+        }
+
+        // Only the empty input is left.
+        search(&tokens, n, k, |_, window| Reverse(window[0].clone()))
+    }
+}
+
+/// Best-first search for `k` inputs of length `n`.
+/// `key(i, window)` must bound, in the order of the queue, the number
+/// of parses of every completion of a prefix of length `i`, and be
+/// exact when `i == n`.
+fn search<I, K>(tokens: &Tokens<I>,
+                n: usize,
+                k: usize,
+                key: impl Fn(usize, &[BigUint]) -> K) -> Vec<(Vec<I>, BigUint)>
+    where I: Ord + Eq + Hash + Clone,
+          K: Ord
+{
+    let mut found = Vec::new();
+    let mut queue = BinaryHeap::new();
+    let window = vec![BigUint::one()];
+    queue.push(Node { key: key(0, &window), input: Vec::new(), window });
+
+    while found.len() < k {
+        let node = match queue.pop() {
+            Some(node) => node,
+            None => break
+        };
+        if node.input.len() == n {
+            found.push((node.input, node.window.last().unwrap().clone()));
+            continue;
+        }
+        for symbol in &tokens.alphabet {
+            let window = tokens.extend(&node.input, &node.window, symbol);
+            let mut input = node.input.clone();
+            input.push(symbol.clone());
+            queue.push(Node { key: key(input.len(), &window), input, window });
+        }
+    }
+    found
+}
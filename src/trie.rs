@@ -0,0 +1,166 @@
+//! Prefix tree of tokens.
+//!
+//! Matching walks down from the root one symbol at a time and stops as
+//! soon as no token continues, instead of looking up every prefix up to
+//! the longest token.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+pub struct Trie<K, V> {
+    /// Node 0 is the root.
+    nodes: Vec<Node<K, V>>,
+
+    /// Nodes with a value, in order of insertion.
+    valued: Vec<usize>
+}
+
+struct Node<K, V> {
+    value: Option<V>,
+
+    /// Position of this node in `valued`.
+    rank: usize,
+    children: HashMap<K, usize>
+}
+
+impl<K: Eq + Hash, V> Node<K, V> {
+    fn new() -> Node<K, V> {
+        Node {
+            value: None,
+            rank: 0,
+            children: HashMap::new()
+        }
+    }
+}
+
+impl<K: Eq + Hash, V> Trie<K, V> {
+    pub fn new() -> Trie<K, V> {
+        Trie {
+            nodes: vec![Node::new()],
+            valued: Vec::new()
+        }
+    }
+
+    pub fn root(&self) -> usize {
+        0
+    }
+
+    /// Replaces any value already stored for `key`.
+    pub fn insert(&mut self, key: impl IntoIterator<Item = K>, value: V) {
+        let node = self.node(key);
+        if self.nodes[node].value.is_none() {
+            self.nodes[node].rank = self.valued.len();
+            self.valued.push(node);
+        }
+        self.nodes[node].value = Some(value);
+    }
+
+    /// Value stored for `key`, inserting `default()` if there is none.
+    pub fn get_or_insert_with(&mut self,
+                              key: impl IntoIterator<Item = K>,
+                              default: impl FnOnce() -> V) -> &mut V {
+        let node = self.node(key);
+        if self.nodes[node].value.is_none() {
+            self.nodes[node].rank = self.valued.len();
+            self.valued.push(node);
+        }
+        self.nodes[node].value.get_or_insert_with(default)
+    }
+
+    /// Node for `key`, creating it and its ancestors as needed.
+    fn node(&mut self, key: impl IntoIterator<Item = K>) -> usize {
+        let mut node = self.root();
+        for k in key {
+            let next = self.nodes.len();
+            node = *self.nodes[node].children.entry(k).or_insert(next);
+            if node == next {
+                self.nodes.push(Node::new());
+            }
+        }
+        node
+    }
+
+    pub fn child(&self, node: usize, k: &K) -> Option<usize> {
+        self.nodes[node].children.get(k).cloned()
+    }
+
+    pub fn value(&self, node: usize) -> Option<&V> {
+        self.nodes[node].value.as_ref()
+    }
+
+    /// Children of `node`, in no particular order.
+    pub fn children<'a>(&'a self, node: usize) -> impl Iterator<Item = usize> + 'a {
+        self.nodes[node].children.values().cloned()
+    }
+
+    /// Order in which the value of `node` was first inserted.
+    pub fn rank(&self, node: usize) -> usize {
+        self.nodes[node].rank
+    }
+
+    /// Every key with its value, in order of first insertion.
+    pub fn entries(&self) -> Vec<(Vec<K>, &V)>
+        where K: Clone
+    {
+        let mut keys = vec![Vec::new(); self.nodes.len()];
+        let mut stack = vec![self.root()];
+        while let Some(node) = stack.pop() {
+            for (k, &child) in &self.nodes[node].children {
+                let mut key = keys[node].clone();
+                key.push(k.clone());
+                keys[child] = key;
+                stack.push(child);
+            }
+        }
+
+        self.valued
+            .iter()
+            .map(|&node| (keys[node].clone(), self.nodes[node].value.as_ref().unwrap()))
+            .collect()
+    }
+
+    /// Every key that is a prefix of `input`, as its length and value,
+    /// shortest first.
+    pub fn prefixes<'a, 'b>(&'a self, input: &'b [K])
+                            -> impl Iterator<Item = (usize, &'a V)> + 'b
+        where 'a: 'b
+    {
+        input
+            .iter()
+            .scan(self.root(), move |node, k| {
+                *node = self.child(*node, k)?;
+                Some(*node)
+            })
+            .enumerate()
+            .filter_map(move |(i, node)| self.value(node).map(|v| (i + 1, v)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Trie;
+
+    #[test]
+    fn prefixes_stop_early() {
+        let mut trie = Trie::new();
+        trie.insert("1".chars(), 'A');
+        trie.insert("12".chars(), 'L');
+        trie.insert("1234567890".chars(), 'X');
+        trie.insert("12".chars(), 'M');
+
+        let input = "123456789".chars().collect::<Vec<char>>();
+        assert_eq!(trie.prefixes(&input).collect::<Vec<_>>(),
+                   vec![(1, &'A'), (2, &'M')]);
+
+        let input = "12345678901".chars().collect::<Vec<char>>();
+        assert_eq!(trie.prefixes(&input).map(|(i, _)| i).collect::<Vec<_>>(),
+                   vec![1, 2, 10]);
+
+        assert_eq!(trie.prefixes(&['9']).count(), 0);
+
+        assert_eq!(trie.entries(),
+                   vec![("1".chars().collect(), &'A'),
+                        ("12".chars().collect(), &'M'),
+                        ("1234567890".chars().collect(), &'X')])
+    }
+}
@@ -0,0 +1,93 @@
+//! Check a `Config` for entries that `Parser::new` would silently
+//! drop or that can never match.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Config;
+
+/// Problem with a `Config`. Indices are positions in the `Config`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// The empty token would match without consuming any input.
+    EmptyToken { index: usize },
+
+    /// Token containing whitespace, such as `"1 "`.
+    WhitespaceInToken { index: usize, token: String },
+
+    /// Token listed more than once with the same output.
+    DuplicateToken { token: String, indices: Vec<usize> },
+
+    /// Token listed more than once with different outputs;
+    /// only the last one would be used.
+    ConflictingMapping { token: String, entries: Vec<(usize, char)> }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Diagnostic::EmptyToken { index } =>
+                write!(f, "entry {}: empty token", index),
+            Diagnostic::WhitespaceInToken { index, ref token } =>
+                write!(f, "entry {}: whitespace in token {:?}", index, token),
+            Diagnostic::DuplicateToken { ref token, ref indices } =>
+                write!(f, "entries {:?}: duplicate token {:?}", indices, token),
+            Diagnostic::ConflictingMapping { ref token, ref entries } => {
+                let outputs = entries
+                    .iter()
+                    .map(|&(_, c)| c)
+                    .collect::<Vec<char>>();
+                let indices = entries
+                    .iter()
+                    .map(|&(i, _)| i)
+                    .collect::<Vec<usize>>();
+                write!(f, "entries {:?}: token {:?} maps to each of {:?}",
+                       indices, token, outputs)
+            }
+        }
+    }
+}
+
+/// Everything wrong with `config`, in order of first appearance.
+/// An empty result means `Parser::new` uses every entry as given.
+pub fn validate_config(config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    // Entries for each token, keyed by the index of its first entry.
+    let mut first_index = HashMap::new();
+    let mut entries: HashMap<usize, Vec<(usize, char)>> = HashMap::new();
+
+    for (index, &(ref token, c)) in config.iter().enumerate() {
+        if token.is_empty() {
+            diagnostics.push((index, Diagnostic::EmptyToken { index }));
+        } else if token.chars().any(char::is_whitespace) {
+            diagnostics.push((index, Diagnostic::WhitespaceInToken {
+                index,
+                token: token.clone()
+            }));
+        }
+
+        let first = *first_index.entry(token).or_insert(index);
+        entries.entry(first).or_default().push((index, c));
+    }
+
+    for (first, entries) in entries {
+        if entries.len() < 2 {
+            continue;
+        }
+        let token = config[first].0.clone();
+        let diagnostic = if entries.iter().all(|&(_, c)| c == entries[0].1) {
+            Diagnostic::DuplicateToken {
+                token,
+                indices: entries.iter().map(|&(i, _)| i).collect()
+            }
+        } else {
+            Diagnostic::ConflictingMapping { token, entries }
+        };
+        diagnostics.push((first, diagnostic));
+    }
+
+    // Stable, so that problems with a single entry come first.
+    diagnostics.sort_by_key(|&(index, _)| index);
+    diagnostics.into_iter().map(|(_, d)| d).collect()
+}